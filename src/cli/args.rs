@@ -0,0 +1,165 @@
+use multiproof::proof::types::{MemoryLayout, PageAddress, ProofHash, HASH_SIZE};
+use std::collections::HashMap;
+
+/// Options that don't take a value.
+const FLAGS: &[&str] = &["pristine", "levels", "json", "cartesi"];
+
+/// Command line arguments: positional arguments and `--name value` options.
+pub struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if FLAGS.contains(&name) => {
+                    options.insert(name.to_string(), String::new());
+                }
+                Some(name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("missing value for --{}", name))?;
+                    options.insert(name.to_string(), value.clone());
+                }
+                None => positional.push(arg.clone()),
+            }
+        }
+        Ok(Self {
+            positional,
+            options,
+        })
+    }
+
+    /// Returns the positional argument at `index`.
+    pub fn positional(&self, index: usize, name: &str) -> Result<&str, String> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| format!("missing argument <{}>", name))
+    }
+
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    /// Returns the memory layout given by `--memory-log2` and `--page-log2`, falling back to
+    /// the default one, or to the one of the Cartesi machine with `--cartesi`.
+    pub fn layout(&self) -> Result<MemoryLayout, String> {
+        let default = if self.flag("cartesi") {
+            MemoryLayout::CARTESI
+        } else {
+            MemoryLayout::default()
+        };
+        let parse = |name: &str, default: usize| {
+            self.option(name).map_or(Ok(default), |value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("invalid value for --{}: {}", name, value))
+            })
+        };
+        let memory_log2_size = parse("memory-log2", default.memory_log2_size())?;
+        let page_log2_size = parse("page-log2", default.page_log2_size())?;
+        MemoryLayout::try_new(memory_log2_size, page_log2_size).map_err(|error| error.to_string())
+    }
+}
+
+/// Parses a hexadecimal address, with or without the `0x` prefix.
+pub fn parse_address(value: &str) -> Result<PageAddress, String> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    PageAddress::from_str_radix(digits, 16).map_err(|_| format!("invalid address: {}", value))
+}
+
+/// Parses hexadecimal data, with or without the `0x` prefix.
+fn parse_hex(value: &str) -> Result<Vec<u8>, String> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits: {}", value));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            digits
+                .get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format!("invalid hex: {}", value))
+        })
+        .collect()
+}
+
+/// Parses a hexadecimal hash, with or without the `0x` prefix.
+pub fn parse_hash(value: &str) -> Result<ProofHash, String> {
+    parse_hex(value)?
+        .try_into()
+        .map_err(|_| format!("a hash must be {} bytes long: {}", HASH_SIZE, value))
+}
+
+/// Formats data as `0x`-prefixed hex.
+pub fn to_hex(data: &[u8]) -> String {
+    let mut hex = String::with_capacity(2 + data.len() * 2);
+    hex.push_str("0x");
+    for byte in data {
+        hex.push_str(&format!("{:02x}", byte));
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Args {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Args::parse(&args).unwrap()
+    }
+
+    #[test_log::test]
+    fn test_parse_args() {
+        let args = args(&["prove", "image.bin", "--pages", "0x0,0x10", "--pristine"]);
+        assert_eq!(args.positional(1, "image"), Ok("image.bin"));
+        assert!(args.positional(2, "proof").is_err());
+        assert_eq!(args.option("pages"), Some("0x0,0x10"));
+        assert!(args.flag("pristine"));
+        assert_eq!(args.layout(), Ok(MemoryLayout::default()));
+    }
+
+    #[test_log::test]
+    fn test_parse_cartesi() {
+        assert_eq!(args(&["--cartesi"]).layout(), Ok(MemoryLayout::CARTESI));
+        assert_eq!(
+            args(&["--cartesi", "--page-log2", "3"]).layout(),
+            Ok(MemoryLayout::new(64, 3))
+        );
+    }
+
+    #[test_log::test]
+    fn test_parse_layout() {
+        assert_eq!(
+            args(&["--memory-log2", "64", "--page-log2", "12"]).layout(),
+            Ok(MemoryLayout::new(64, 12))
+        );
+        assert!(args(&["--memory-log2", "65"]).layout().is_err());
+        assert!(args(&["--memory-log2", "4", "--page-log2", "5"])
+            .layout()
+            .is_err());
+    }
+
+    #[test_log::test]
+    fn test_hex() {
+        assert_eq!(parse_address("0x1f"), Ok(0x1f));
+        assert_eq!(parse_address("1f"), Ok(0x1f));
+        assert_eq!(parse_hex("0x00ff"), Ok(vec![0x00, 0xff]));
+        assert!(parse_hex("0x0").is_err());
+        assert!(parse_hex("0xzz").is_err());
+        assert!(parse_hash("0x00").is_err());
+        assert_eq!(to_hex(&[0x00, 0xff]), "0x00ff");
+    }
+}
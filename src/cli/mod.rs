@@ -0,0 +1,238 @@
+mod args;
+
+use self::args::{parse_address, parse_hash, to_hex, Args};
+use multiproof::proof::{
+    codec::{Decode, Encode, ProofBundle, MAGIC},
+    hasher::{Cartesi, HashAlgorithm, Keccak256, MerkleHasher},
+    merkle_proof::MerkleProof,
+    prover::Prover,
+    stream::root_from_reader,
+    types::MemoryLayout,
+};
+use std::{
+    error::Error,
+    fs,
+    io::{self, Write},
+};
+
+const USAGE: &str = "\
+Usage: merkle-proof <command> [options]
+
+Commands:
+  root <image>                    calculate the root of a raw memory image
+  prove <image> --pages <list>    generate a proof for the comma-separated page addresses
+  verify <proof> --root <hash>    verify a proof against the expected root
+  inspect <proof>                 print the pages and the multiproof entries of a proof
+  convert <proof>                 convert a proof between the binary and the JSON formats
+
+Proofs are read in either format. JSON needs the serde feature.
+
+Options:
+  --memory-log2 <n>   log2 of the memory size (root, prove)
+  --page-log2 <n>     log2 of the page size (root, prove)
+  --hash <name>       keccak256 (default), sha256, blake3 or cartesi (root, prove); sha256 and
+                      blake3 need the sha2 and blake3 features
+  --cartesi           use the Cartesi machine tree: 2^64 bytes of memory, 4 KiB pages and
+                      Keccak-256 over 8-byte words (root, prove)
+  --output <file>     write the proof to the file instead of stdout (prove, convert)
+  --json              write the proof as JSON instead of binary (prove, convert)
+  --pristine          omit (prove) or assume (verify) the pristine subtrees
+  --levels            print the known nodes of every level of the tree (verify)
+";
+
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+/// Runs `$function::<H>($args)` with the hasher `H` selected by `$hash`. Fails if the hasher
+/// is not enabled in this build.
+macro_rules! with_hasher {
+    ($hash:expr, $function:ident($($args:expr),*)) => {
+        match $hash {
+            HashAlgorithm::Keccak256 => $function::<Keccak256>($($args),*),
+            #[cfg(feature = "sha2")]
+            HashAlgorithm::Sha256 => {
+                $function::<multiproof::proof::hasher::Sha256>($($args),*)
+            }
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => {
+                $function::<multiproof::proof::hasher::Blake3>($($args),*)
+            }
+            HashAlgorithm::Cartesi => $function::<Cartesi>($($args),*),
+            #[allow(unreachable_patterns)]
+            hash => Err(format!(
+                "{} is not enabled in this build, see the `sha2` and `blake3` features",
+                hash.name()
+            )
+            .into()),
+        }
+    };
+}
+
+pub fn run(args: &[String]) -> CliResult<()> {
+    let args = Args::parse(args)?;
+    let default_hash = if args.flag("cartesi") {
+        "cartesi"
+    } else {
+        "keccak256"
+    };
+    let hash = args.option("hash").unwrap_or(default_hash);
+    let hash =
+        HashAlgorithm::from_name(hash).ok_or_else(|| format!("unknown hash function: {}", hash))?;
+    match args.positional(0, "command") {
+        Ok("root") => with_hasher!(hash, root(&args)),
+        Ok("prove") => with_hasher!(hash, prove(&args)),
+        Ok("verify") => {
+            let proof = read_proof(&args)?;
+            with_hasher!(proof.hash_algorithm, verify(&args, proof))
+        }
+        Ok("inspect") => {
+            let proof = read_proof(&args)?;
+            with_hasher!(proof.hash_algorithm, inspect(proof))
+        }
+        Ok("convert") => write_proof(&args, &read_proof(&args)?),
+        Ok("help") => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+/// Reads the memory image, which may be shorter than the memory chunk.
+fn read_image(args: &Args, layout: &MemoryLayout) -> CliResult<Vec<u8>> {
+    let image = fs::read(args.positional(1, "image")?)?;
+    if image.len() as u128 > layout.last_address() as u128 + 1 {
+        return Err(format!(
+            "the image doesn't fit into 2^{} bytes",
+            layout.memory_log2_size()
+        )
+        .into());
+    }
+    Ok(image)
+}
+
+/// Reads the proof, which is JSON unless it starts with the binary magic bytes.
+fn read_proof(args: &Args) -> CliResult<ProofBundle> {
+    let input = fs::read(args.positional(1, "proof")?)?;
+    if input.starts_with(&MAGIC) {
+        return Ok(ProofBundle::decode(&input)?);
+    }
+    read_json(&input)
+}
+
+#[cfg(feature = "serde")]
+fn read_json(input: &[u8]) -> CliResult<ProofBundle> {
+    let input = std::str::from_utf8(input).map_err(|_| "the proof is neither binary nor JSON")?;
+    Ok(serde_json::from_str(input)?)
+}
+
+#[cfg(not(feature = "serde"))]
+fn read_json(_input: &[u8]) -> CliResult<ProofBundle> {
+    Err("the proof is not binary, JSON proofs need the `serde` feature".into())
+}
+
+#[cfg(feature = "serde")]
+fn write_json(proof: &ProofBundle) -> CliResult<Vec<u8>> {
+    let mut json = serde_json::to_vec_pretty(proof)?;
+    json.push(b'\n');
+    Ok(json)
+}
+
+#[cfg(not(feature = "serde"))]
+fn write_json(_proof: &ProofBundle) -> CliResult<Vec<u8>> {
+    Err("--json needs the `serde` feature".into())
+}
+
+/// Writes the proof in the binary format, or as JSON with `--json`.
+fn write_proof(args: &Args, proof: &ProofBundle) -> CliResult<()> {
+    let output = if args.flag("json") {
+        write_json(proof)?
+    } else {
+        proof.encode()
+    };
+    match args.option("output") {
+        Some(path) => fs::write(path, output)?,
+        None => io::stdout().write_all(&output)?,
+    }
+    Ok(())
+}
+
+fn root<H: MerkleHasher>(args: &Args) -> CliResult<()> {
+    let layout = args.layout()?;
+    // the missing part of the image is pristine
+    let image = fs::File::open(args.positional(1, "image")?)?;
+    println!("{}", to_hex(&root_from_reader::<H>(image, &layout)?));
+    Ok(())
+}
+
+fn prove<H: MerkleHasher>(args: &Args) -> CliResult<()> {
+    let layout = args.layout()?;
+    // the missing part of the image is pristine
+    let image = read_image(args, &layout)?;
+    let pages = args
+        .option("pages")
+        .ok_or("missing option --pages")?
+        .split(',')
+        .filter(|address| !address.is_empty())
+        .map(parse_address)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let prover = Prover::<H>::with_hasher(layout, &image)?.pristine(args.flag("pristine"));
+    let (page_cache, multiproof) = prover.generate(&pages)?;
+    let proof = ProofBundle {
+        layout,
+        hash_algorithm: H::ALGORITHM,
+        page_cache,
+        multiproof,
+    };
+    write_proof(args, &proof)
+}
+
+fn verify<H: MerkleHasher>(args: &Args, proof: ProofBundle) -> CliResult<()> {
+    let expected_root = parse_hash(args.option("root").ok_or("missing option --root")?)?;
+    let mut merkle_proof =
+        MerkleProof::<H>::with_hasher(proof.layout, proof.page_cache, proof.multiproof)
+            .pristine(args.flag("pristine"))
+            .retain_levels(args.flag("levels"));
+    let result = merkle_proof.verify(&expected_root);
+    // the nodes calculated before a failure help to find where the proofs diverge
+    for (level, (address_low, address_high), hash, source) in merkle_proof.nodes() {
+        println!(
+            "  level {:>2}  {:#018x} - {:#018x}  {}  {:?}",
+            level,
+            address_low,
+            address_high,
+            to_hex(&hash),
+            source
+        );
+    }
+    result?;
+    println!("ok");
+    Ok(())
+}
+
+fn inspect<H: MerkleHasher>(proof: ProofBundle) -> CliResult<()> {
+    let layout = proof.layout;
+    println!(
+        "memory: 2^{} bytes, page: 2^{} bytes, depth: {}, hash: {}",
+        layout.memory_log2_size(),
+        layout.page_log2_size(),
+        layout.depth(),
+        proof.hash_algorithm.name()
+    );
+    println!("pages: {}", proof.page_cache.len());
+    for page in proof.page_cache.iter() {
+        println!("  {:#018x}  {}", page.address, to_hex(&page.hash::<H>()));
+    }
+    println!("multiproof entries: {}", proof.multiproof.len());
+    for entry in proof.multiproof.iter() {
+        let level = entry.node(&layout).map(|(level, _)| level);
+        println!(
+            "  level {:>2}  {:#018x} - {:#018x}  {}",
+            level.map_or("?".to_string(), |level| level.to_string()),
+            entry.address_low,
+            entry.address_high,
+            to_hex(&entry.hash)
+        );
+    }
+    Ok(())
+}
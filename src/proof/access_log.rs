@@ -0,0 +1,226 @@
+use crate::proof::{
+    error::{AccessError, ProofError},
+    hasher::MerkleHasher,
+    merkle_proof::MerkleProof,
+    multiproof::Multiproof,
+    page_cache::{Page, PageCache},
+    types::{MemoryLayout, PageAddress, PageData, ProofHash},
+};
+
+/// A logged access to the page at `address`. It carries the multiproof complementing the page
+/// to the root of the memory before the access, so it can be verified on its own. With a word
+/// hasher, the accesses may be word-level ones on the layout returned by
+/// `word_proof::word_layout`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Access {
+    pub address: PageAddress,
+    /// Contents of the page before the access.
+    pub old_value: PageData,
+    /// Contents written to the page, or `None` for a read.
+    pub new_value: Option<PageData>,
+    pub multiproof: Multiproof,
+}
+
+impl Access {
+    pub fn read(address: PageAddress, value: PageData, multiproof: Multiproof) -> Self {
+        Self {
+            address,
+            old_value: value,
+            new_value: None,
+            multiproof,
+        }
+    }
+
+    pub fn write(
+        address: PageAddress,
+        old_value: PageData,
+        new_value: PageData,
+        multiproof: Multiproof,
+    ) -> Self {
+        Self {
+            address,
+            old_value,
+            new_value: Some(new_value),
+            multiproof,
+        }
+    }
+
+    pub fn is_read(&self) -> bool {
+        self.new_value.is_none()
+    }
+
+    /// Checks the access against `root`, the root of the memory before the access, and returns
+    /// the root after it. The page and the multiproof must lead to `root` exactly, without
+    /// redundant data.
+    pub fn apply<H: MerkleHasher>(
+        &self,
+        layout: &MemoryLayout,
+        root: &ProofHash,
+    ) -> Result<ProofHash, ProofError> {
+        let page_cache = PageCache::try_new(
+            layout,
+            vec![Page {
+                data: self.old_value.clone(),
+                address: self.address,
+            }],
+        )?;
+        let mut merkle_proof =
+            MerkleProof::<H>::with_hasher(*layout, page_cache, &self.multiproof).strict(true);
+        let Some(new_value) = &self.new_value else {
+            merkle_proof.verify(root)?;
+            return Ok(*root);
+        };
+        let (old_root, new_root) =
+            merkle_proof.apply_writes([(self.address, new_value.clone())])?;
+        if old_root != *root {
+            return Err(ProofError::RootMismatch {
+                expected: *root,
+                computed: old_root,
+            });
+        }
+        Ok(new_root)
+    }
+}
+
+/// Replays the access log starting from the memory with the given `root`, checking every
+/// access against the root left by the previous ones. Returns the root after the last access,
+/// or the index of the first access that fails.
+pub fn replay<H: MerkleHasher>(
+    layout: &MemoryLayout,
+    root: &ProofHash,
+    log: &[Access],
+) -> Result<ProofHash, AccessError> {
+    log.iter()
+        .enumerate()
+        .try_fold(*root, |root, (index, access)| {
+            log::debug!(
+                "Replaying access {}, page address: {:x}, read: {}",
+                index,
+                access.address,
+                access.is_read()
+            );
+            access
+                .apply::<H>(layout, &root)
+                .map_err(|error| AccessError { index, error })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::{
+        hasher::{Cartesi, Keccak256},
+        merkle_tree::MerkleTree,
+        types::HASH_SIZE,
+        word_proof::word_layout,
+    };
+
+    fn test_log(layout: MemoryLayout) -> (ProofHash, Vec<Access>, ProofHash) {
+        let mut tree = MerkleTree::<Keccak256>::with_hasher(layout);
+        tree.update_pages([(0x40, vec![4u8; 16]), (0x3f0, vec![63u8; 16])])
+            .unwrap();
+        let start_root = tree.root();
+        let log = vec![
+            tree.log_read(0x40).unwrap(),
+            tree.log_write(0x50, vec![5u8; 16]).unwrap(),
+            tree.log_read(0x50).unwrap(),
+            tree.log_write(0x40, vec![0u8; 16]).unwrap(),
+            tree.log_read(0x200).unwrap(),
+            tree.log_write(0x3f0, vec![1u8; 16]).unwrap(),
+        ];
+        (start_root, log, tree.root())
+    }
+
+    #[test_log::test]
+    fn test_replay() {
+        let layout = MemoryLayout::new(10, 4);
+        let (start_root, log, end_root) = test_log(layout);
+        assert_eq!(log.iter().filter(|access| access.is_read()).count(), 3);
+        assert_eq!(
+            replay::<Keccak256>(&layout, &start_root, &log),
+            Ok(end_root)
+        );
+        assert_eq!(
+            replay::<Keccak256>(&layout, &start_root, &[]),
+            Ok(start_root)
+        );
+        // the log may be replayed in parts
+        let middle_root = replay::<Keccak256>(&layout, &start_root, &log[..3]).unwrap();
+        assert_eq!(
+            replay::<Keccak256>(&layout, &middle_root, &log[3..]),
+            Ok(end_root)
+        );
+
+        // a read of a value that was never written
+        let mut tampered = log.clone();
+        tampered[2].old_value = vec![6u8; 16];
+        assert!(matches!(
+            replay::<Keccak256>(&layout, &start_root, &tampered),
+            Err(AccessError {
+                index: 2,
+                error: ProofError::RootMismatch { .. }
+            })
+        ));
+
+        // the log is missing a write, so the next access sees a different memory
+        let mut skipped = log.clone();
+        skipped.remove(1);
+        assert!(matches!(
+            replay::<Keccak256>(&layout, &start_root, &skipped),
+            Err(AccessError {
+                index: 1,
+                error: ProofError::RootMismatch { .. }
+            })
+        ));
+
+        let mut incomplete = log.clone();
+        incomplete[3].multiproof.remove(0x0, 0x3f);
+        assert_eq!(
+            replay::<Keccak256>(&layout, &start_root, &incomplete),
+            Err(AccessError {
+                index: 3,
+                error: ProofError::MissingNode {
+                    level: 2,
+                    address_low: 0x0,
+                    address_high: 0x3f,
+                }
+            })
+        );
+
+        let mut invalid = log;
+        invalid[5].new_value = Some(vec![1u8; 8]);
+        assert_eq!(
+            replay::<Keccak256>(&layout, &start_root, &invalid)
+                .unwrap_err()
+                .to_string(),
+            "access 5 failed: invalid size of page 3f0: 8 bytes"
+        );
+        assert!(replay::<Keccak256>(&layout, &[0u8; HASH_SIZE], &invalid).is_err());
+    }
+
+    #[test_log::test]
+    fn test_replay_words() {
+        // with a word hasher the log may record single words
+        let layout = word_layout::<Cartesi>(&MemoryLayout::CARTESI).unwrap();
+        let mut tree = MerkleTree::<Cartesi>::with_hasher(layout);
+        let start_root = tree.root();
+        let log = vec![
+            tree.log_write(0x80000000, 0x13u64.to_le_bytes().to_vec())
+                .unwrap(),
+            tree.log_read(0x80000008).unwrap(),
+            tree.log_write(0x80000000, 0x73u64.to_le_bytes().to_vec())
+                .unwrap(),
+        ];
+        assert_eq!(log[2].old_value, 0x13u64.to_le_bytes());
+        assert_eq!(
+            replay::<Cartesi>(&layout, &start_root, &log),
+            Ok(tree.root())
+        );
+
+        let mut page_tree = MerkleTree::<Cartesi>::with_hasher(MemoryLayout::CARTESI);
+        let mut page = vec![0u8; 1 << 12];
+        page[..8].copy_from_slice(&0x73u64.to_le_bytes());
+        page_tree.update_page(0x80000000, page).unwrap();
+        assert_eq!(page_tree.root(), tree.root());
+    }
+}
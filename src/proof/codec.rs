@@ -0,0 +1,672 @@
+use crate::proof::{
+    error::{DecodeError, ProofError},
+    hasher::HashAlgorithm,
+    multiproof::{Multiproof, MultiproofEntry},
+    page_cache::{Page, PageCache},
+    types::{MemoryLayout, PageAddress, HASH_SIZE},
+};
+
+/// Magic bytes that start a serialized proof bundle.
+pub const MAGIC: [u8; 4] = *b"MPRF";
+/// Current version of the proof bundle encoding.
+pub const VERSION: u8 = 1;
+
+/// Binary encoding of the proof data. All the integers are little-endian, variable-size values
+/// are prefixed with their `u32` length:
+///
+/// - `MultiproofEntry`: `address_low: u64`, `address_high: u64`, `hash: [u8; 32]`;
+/// - `Multiproof`: `count: u32`, followed by the entries sorted by their ranges;
+/// - `Page`: `address: u64`, `length: u32`, `data: [u8; length]`;
+/// - `PageCache`: `count: u32`, followed by the pages in ascending address order;
+/// - `ProofBundle`: `magic: [u8; 4]`, `version: u8`, `memory_log2_size: u8`,
+///   `page_log2_size: u8`, `hash_algorithm: u8`, followed by the page cache and the multiproof.
+pub trait Encode {
+    /// Appends the encoded value to `output`.
+    fn encode_to(&self, output: &mut Vec<u8>);
+
+    fn encode(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        self.encode_to(&mut output);
+        output
+    }
+}
+
+/// Decoding of the values serialized with `Encode`.
+pub trait Decode: Sized {
+    /// Decodes a value from the beginning of `input` and advances it past the value.
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError>;
+
+    /// Decodes a value that takes the whole `input`.
+    fn decode(mut input: &[u8]) -> Result<Self, DecodeError> {
+        let value = Self::decode_from(&mut input)?;
+        if !input.is_empty() {
+            return Err(DecodeError::TrailingBytes { count: input.len() });
+        }
+        Ok(value)
+    }
+}
+
+fn read_bytes<'a>(input: &mut &'a [u8], count: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < count {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    let (bytes, rest) = input.split_at(count);
+    *input = rest;
+    Ok(bytes)
+}
+
+fn read_u8(input: &mut &[u8]) -> Result<u8, DecodeError> {
+    Ok(read_bytes(input, 1)?[0])
+}
+
+fn read_u32(input: &mut &[u8]) -> Result<u32, DecodeError> {
+    Ok(u32::from_le_bytes(
+        read_bytes(input, 4)?.try_into().unwrap(),
+    ))
+}
+
+fn read_u64(input: &mut &[u8]) -> Result<u64, DecodeError> {
+    Ok(u64::from_le_bytes(
+        read_bytes(input, 8)?.try_into().unwrap(),
+    ))
+}
+
+/// Reads the number of items that follow, each taking at least `min_size` bytes. The count is
+/// checked against the input length so that malformed input can't trigger huge allocations.
+fn read_count(input: &mut &[u8], min_size: usize) -> Result<usize, DecodeError> {
+    let count = read_u32(input)? as usize;
+    if count.saturating_mul(min_size) > input.len() {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    Ok(count)
+}
+
+fn encode_length(output: &mut Vec<u8>, length: usize) {
+    let length = u32::try_from(length).expect("too many items to encode");
+    output.extend_from_slice(&length.to_le_bytes());
+}
+
+impl Encode for MultiproofEntry {
+    fn encode_to(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&self.address_low.to_le_bytes());
+        output.extend_from_slice(&self.address_high.to_le_bytes());
+        output.extend_from_slice(&self.hash);
+    }
+}
+
+impl Decode for MultiproofEntry {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let address_low = read_u64(input)?;
+        let address_high = read_u64(input)?;
+        if address_high < address_low {
+            return Err(DecodeError::Invalid(ProofError::InvalidEntryRange {
+                address_low,
+                address_high,
+            }));
+        }
+        Ok(Self {
+            address_low,
+            address_high,
+            hash: read_bytes(input, HASH_SIZE)?.try_into().unwrap(),
+        })
+    }
+}
+
+impl Encode for Multiproof {
+    fn encode_to(&self, output: &mut Vec<u8>) {
+        encode_length(output, self.len());
+        for entry in self.iter() {
+            entry.encode_to(output);
+        }
+    }
+}
+
+impl Decode for Multiproof {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let count = read_count(input, 16 + HASH_SIZE)?;
+        let mut multiproof = Multiproof::new();
+        for _ in 0..count {
+            multiproof
+                .insert(MultiproofEntry::decode_from(input)?)
+                .map_err(DecodeError::Invalid)?;
+        }
+        Ok(multiproof)
+    }
+}
+
+impl Encode for Page {
+    fn encode_to(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&self.address.to_le_bytes());
+        encode_length(output, self.data.len());
+        output.extend_from_slice(&self.data);
+    }
+}
+
+impl Decode for Page {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let address: PageAddress = read_u64(input)?;
+        let length = read_u32(input)? as usize;
+        Ok(Self {
+            data: read_bytes(input, length)?.to_vec(),
+            address,
+        })
+    }
+}
+
+impl Encode for PageCache {
+    fn encode_to(&self, output: &mut Vec<u8>) {
+        encode_length(output, self.len());
+        for page in self.iter() {
+            page.encode_to(output);
+        }
+    }
+}
+
+fn decode_pages(input: &mut &[u8]) -> Result<Vec<Page>, DecodeError> {
+    let count = read_count(input, 12)?;
+    (0..count).map(|_| Page::decode_from(input)).collect()
+}
+
+/// Decodes the pages without a memory layout, so only the duplicate addresses are rejected. The
+/// pages of a proof bundle are checked against its layout as well.
+impl Decode for PageCache {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let page_cache = PageCache::new(decode_pages(input)?);
+        let addresses: Vec<PageAddress> = page_cache.iter().map(|page| page.address).collect();
+        match addresses.windows(2).find(|pair| pair[0] == pair[1]) {
+            Some(pair) => Err(DecodeError::Invalid(ProofError::OverlappingPages {
+                address: pair[0],
+            })),
+            None => Ok(page_cache),
+        }
+    }
+}
+
+/// Creates the memory layout of a decoded proof bundle.
+pub(crate) fn decode_layout(
+    memory_log2_size: u8,
+    page_log2_size: u8,
+) -> Result<MemoryLayout, DecodeError> {
+    MemoryLayout::try_new(memory_log2_size as usize, page_log2_size as usize).map_err(|_| {
+        DecodeError::InvalidLayout {
+            memory_log2_size,
+            page_log2_size,
+        }
+    })
+}
+
+/// Everything needed to verify a proof: the memory layout, the hash function, the pages and the
+/// multiproof.
+pub struct ProofBundle {
+    pub layout: MemoryLayout,
+    pub hash_algorithm: HashAlgorithm,
+    pub page_cache: PageCache,
+    pub multiproof: Multiproof,
+}
+
+impl Encode for ProofBundle {
+    fn encode_to(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&MAGIC);
+        output.push(VERSION);
+        output.push(self.layout.memory_log2_size() as u8);
+        output.push(self.layout.page_log2_size() as u8);
+        output.push(self.hash_algorithm.id());
+        self.page_cache.encode_to(output);
+        self.multiproof.encode_to(output);
+    }
+}
+
+impl Decode for ProofBundle {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        if read_bytes(input, MAGIC.len()).map_err(|_| DecodeError::InvalidMagic)? != MAGIC {
+            return Err(DecodeError::InvalidMagic);
+        }
+        let version = read_u8(input)?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion { version });
+        }
+        let memory_log2_size = read_u8(input)?;
+        let page_log2_size = read_u8(input)?;
+        let layout = decode_layout(memory_log2_size, page_log2_size)?;
+        let id = read_u8(input)?;
+        let hash_algorithm =
+            HashAlgorithm::from_id(id).ok_or(DecodeError::UnknownHashAlgorithm { id })?;
+
+        let page_cache =
+            PageCache::try_new(&layout, decode_pages(input)?).map_err(DecodeError::Invalid)?;
+        let multiproof = Multiproof::decode_from(input)?;
+        multiproof.validate(&layout).map_err(DecodeError::Invalid)?;
+        Ok(Self {
+            layout,
+            hash_algorithm,
+            page_cache,
+            multiproof,
+        })
+    }
+}
+
+/// Serialized form of a proof bundle: the same fields as the binary encoding, the pages and the
+/// multiproof being lists.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BundleFields<P, M> {
+    version: u8,
+    memory_log2_size: u8,
+    page_log2_size: u8,
+    hash_algorithm: HashAlgorithm,
+    pages: P,
+    multiproof: M,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ProofBundle {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        BundleFields {
+            version: VERSION,
+            memory_log2_size: self.layout.memory_log2_size() as u8,
+            page_log2_size: self.layout.page_log2_size() as u8,
+            hash_algorithm: self.hash_algorithm,
+            pages: &self.page_cache,
+            multiproof: &self.multiproof,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Deserializes a proof bundle, checking it the same way as `Decode`.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ProofBundle {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fields = BundleFields::<Vec<Page>, Multiproof>::deserialize(deserializer)?;
+        let bundle = || {
+            if fields.version != VERSION {
+                return Err(DecodeError::UnsupportedVersion {
+                    version: fields.version,
+                });
+            }
+            let layout = decode_layout(fields.memory_log2_size, fields.page_log2_size)?;
+            let page_cache =
+                PageCache::try_new(&layout, fields.pages).map_err(DecodeError::Invalid)?;
+            fields
+                .multiproof
+                .validate(&layout)
+                .map_err(DecodeError::Invalid)?;
+            Ok(Self {
+                layout,
+                hash_algorithm: fields.hash_algorithm,
+                page_cache,
+                multiproof: fields.multiproof,
+            })
+        };
+        bundle().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::{merkle_proof::MerkleProof, prover::Prover};
+
+    fn test_bundle() -> (ProofBundle, Vec<u8>) {
+        let layout = MemoryLayout::new(8, 4);
+        let image: Vec<u8> = (0..1 << 8).map(|i| i as u8).collect();
+        let prover = Prover::new(layout, &image).unwrap();
+        let (page_cache, multiproof) = prover.generate(&[0x10, 0x80]).unwrap();
+        let bundle = ProofBundle {
+            layout,
+            hash_algorithm: HashAlgorithm::Keccak256,
+            page_cache,
+            multiproof,
+        };
+        let encoded = bundle.encode();
+        (bundle, encoded)
+    }
+
+    #[test_log::test]
+    fn test_round_trip() {
+        let (bundle, encoded) = test_bundle();
+        assert_eq!(&encoded[..8], b"MPRF\x01\x08\x04\x01");
+
+        let decoded = ProofBundle::decode(&encoded).unwrap();
+        assert_eq!(decoded.layout, bundle.layout);
+        assert_eq!(decoded.hash_algorithm, bundle.hash_algorithm);
+        assert_eq!(decoded.encode(), encoded);
+
+        let image: Vec<u8> = (0..1 << 8).map(|i| i as u8).collect();
+        let root = Prover::new(bundle.layout, &image).unwrap().root();
+        let mut merkle_proof =
+            MerkleProof::new(decoded.layout, decoded.page_cache, decoded.multiproof);
+        assert_eq!(merkle_proof.verify(&root), Ok(()));
+    }
+
+    fn encode_bundle(pages: Vec<Page>, entries: Vec<MultiproofEntry>) -> Vec<u8> {
+        ProofBundle {
+            layout: MemoryLayout::new(8, 4),
+            hash_algorithm: HashAlgorithm::Keccak256,
+            page_cache: PageCache::new(pages),
+            multiproof: Multiproof::try_new(entries).unwrap(),
+        }
+        .encode()
+    }
+
+    #[test_log::test]
+    fn test_invalid_data() {
+        let page = |address| Page {
+            data: vec![1u8; 16],
+            address,
+        };
+        let entry = |address_low, address_high| MultiproofEntry {
+            address_low,
+            address_high,
+            hash: [0; HASH_SIZE],
+        };
+        assert!(
+            ProofBundle::decode(&encode_bundle(vec![page(0x10)], vec![entry(0x0, 0x0)])).is_ok()
+        );
+
+        let cases = [
+            (
+                encode_bundle(vec![page(0x11)], vec![]),
+                DecodeError::Invalid(ProofError::MisalignedPage { address: 0x11 }),
+            ),
+            (
+                encode_bundle(vec![page(0x100)], vec![]),
+                DecodeError::Invalid(ProofError::PageOutOfRange { address: 0x100 }),
+            ),
+            (
+                encode_bundle(vec![page(0x10), page(0x10)], vec![]),
+                DecodeError::Invalid(ProofError::OverlappingPages { address: 0x10 }),
+            ),
+            (
+                encode_bundle(vec![page(0x10)], vec![entry(0x3, 0x9)]),
+                DecodeError::Invalid(ProofError::MisalignedEntry {
+                    address_low: 0x3,
+                    address_high: 0x9,
+                }),
+            ),
+            (
+                encode_bundle(vec![page(0x10)], vec![entry(0x100, 0x1ff)]),
+                DecodeError::Invalid(ProofError::EntryOutOfRange {
+                    address_low: 0x100,
+                    address_high: 0x1ff,
+                }),
+            ),
+        ];
+        for (encoded, error) in cases {
+            assert_eq!(ProofBundle::decode(&encoded).err(), Some(error));
+        }
+
+        let mut encoded = 2u32.to_le_bytes().to_vec();
+        page(0x10).encode_to(&mut encoded);
+        page(0x10).encode_to(&mut encoded);
+        assert_eq!(
+            PageCache::decode(&encoded).err(),
+            Some(DecodeError::Invalid(ProofError::OverlappingPages {
+                address: 0x10
+            }))
+        );
+    }
+
+    #[test_log::test]
+    fn test_malformed_input() {
+        let (_, encoded) = test_bundle();
+        for length in 0..encoded.len() {
+            assert!(
+                ProofBundle::decode(&encoded[..length]).is_err(),
+                "length {}",
+                length
+            );
+        }
+
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert_eq!(
+            ProofBundle::decode(&trailing).err(),
+            Some(DecodeError::TrailingBytes { count: 1 })
+        );
+
+        let mut corrupt = encoded.clone();
+        corrupt[0] = b'X';
+        assert_eq!(
+            ProofBundle::decode(&corrupt).err(),
+            Some(DecodeError::InvalidMagic)
+        );
+        let mut corrupt = encoded.clone();
+        corrupt[4] = 2;
+        assert_eq!(
+            ProofBundle::decode(&corrupt).err(),
+            Some(DecodeError::UnsupportedVersion { version: 2 })
+        );
+        let mut corrupt = encoded.clone();
+        corrupt[6] = 9;
+        assert_eq!(
+            ProofBundle::decode(&corrupt).err(),
+            Some(DecodeError::InvalidLayout {
+                memory_log2_size: 8,
+                page_log2_size: 9
+            })
+        );
+        let mut corrupt = encoded.clone();
+        corrupt[6] = 3;
+        assert_eq!(
+            ProofBundle::decode(&corrupt).err(),
+            Some(DecodeError::Invalid(ProofError::InvalidPageSize {
+                address: 0x10,
+                size: 16
+            }))
+        );
+        let mut corrupt = encoded.clone();
+        corrupt[7] = 0;
+        assert_eq!(
+            ProofBundle::decode(&corrupt).err(),
+            Some(DecodeError::UnknownHashAlgorithm { id: 0 })
+        );
+        let mut corrupt = encoded.clone();
+        corrupt[12] = 0x11;
+        assert_eq!(
+            ProofBundle::decode(&corrupt).err(),
+            Some(DecodeError::Invalid(ProofError::MisalignedPage {
+                address: 0x11
+            }))
+        );
+        // the high address of the first entry, after the two pages
+        let mut corrupt = encoded.clone();
+        corrupt[80] = 0x9;
+        assert_eq!(
+            ProofBundle::decode(&corrupt).err(),
+            Some(DecodeError::Invalid(ProofError::MisalignedEntry {
+                address_low: 0x0,
+                address_high: 0x9
+            }))
+        );
+        // a huge page count doesn't allocate
+        let mut corrupt = encoded;
+        corrupt[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            ProofBundle::decode(&corrupt).err(),
+            Some(DecodeError::UnexpectedEnd)
+        );
+
+        let entry = MultiproofEntry {
+            address_low: 0x20,
+            address_high: 0x1f,
+            hash: [0; HASH_SIZE],
+        };
+        assert_eq!(
+            MultiproofEntry::decode(&entry.encode()).err(),
+            Some(DecodeError::Invalid(ProofError::InvalidEntryRange {
+                address_low: 0x20,
+                address_high: 0x1f
+            }))
+        );
+
+        let entry = MultiproofEntry {
+            address_low: 0x10,
+            address_high: 0x1f,
+            hash: [0; HASH_SIZE],
+        };
+        let mut encoded = 2u32.to_le_bytes().to_vec();
+        entry.encode_to(&mut encoded);
+        entry.encode_to(&mut encoded);
+        assert_eq!(
+            Multiproof::decode(&encoded).err(),
+            Some(DecodeError::Invalid(ProofError::DuplicateMultiproofEntry {
+                address_low: 0x10,
+                address_high: 0x1f
+            }))
+        );
+        let mut encoded = 2u32.to_le_bytes().to_vec();
+        entry.encode_to(&mut encoded);
+        MultiproofEntry {
+            hash: [1; HASH_SIZE],
+            ..entry
+        }
+        .encode_to(&mut encoded);
+        assert_eq!(
+            Multiproof::decode(&encoded).err(),
+            Some(DecodeError::Invalid(
+                ProofError::ConflictingMultiproofEntries {
+                    address_low: 0x10,
+                    address_high: 0x1f
+                }
+            ))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test_log::test]
+    fn test_json_round_trip() {
+        let (bundle, encoded) = test_bundle();
+        let json = serde_json::to_string_pretty(&bundle).unwrap();
+        assert!(json.starts_with(
+            "{\n  \"version\": 1,\n  \"memory_log2_size\": 8,\n  \"page_log2_size\": 4,\n  \
+             \"hash_algorithm\": \"keccak256\",\n  \"pages\": [\n    {\n      \
+             \"data\": \"0x101112131415161718191a1b1c1d1e1f\",\n      \"address\": \"0x10\"\n    },"
+        ));
+        let decoded: ProofBundle = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.encode(), encoded);
+
+        let entry = MultiproofEntry {
+            address_low: 0x0,
+            address_high: 0xffff_ffff_ffff_ffff,
+            hash: [0xab; 32],
+        };
+        let json = serde_json::to_string(&entry).unwrap();
+        assert_eq!(
+            serde_json::from_str::<MultiproofEntry>(&json).unwrap(),
+            entry
+        );
+        // the order of the fields and the case of the digits don't matter
+        let compact = format!(
+            "{{\"hash\":\"0x{}\",\"address_high\":\"0xFFFFFFFFFFFFFFFF\",\"address_low\":\"0x0\"}}",
+            "AB".repeat(32)
+        );
+        assert_eq!(
+            serde_json::from_str::<MultiproofEntry>(&compact).unwrap(),
+            entry
+        );
+        assert_eq!(
+            serde_json::from_str::<PageCache>(" [ ] ").unwrap(),
+            PageCache::default()
+        );
+        assert_eq!(serde_json::to_string(&Multiproof::new()).unwrap(), "[]");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test_log::test]
+    fn test_json_malformed_input() {
+        let (bundle, _) = test_bundle();
+        let json = serde_json::to_string_pretty(&bundle).unwrap();
+        for (from, to, error) in [
+            (
+                "\"version\": 1",
+                "\"version\": 2",
+                DecodeError::UnsupportedVersion { version: 2 }.to_string(),
+            ),
+            (
+                "\"page_log2_size\": 4",
+                "\"page_log2_size\": 9",
+                DecodeError::InvalidLayout {
+                    memory_log2_size: 8,
+                    page_log2_size: 9,
+                }
+                .to_string(),
+            ),
+            (
+                "\"page_log2_size\": 4",
+                "\"page_log2_size\": 3",
+                DecodeError::Invalid(ProofError::InvalidPageSize {
+                    address: 0x10,
+                    size: 16,
+                })
+                .to_string(),
+            ),
+            (
+                "\"address\": \"0x10\"",
+                "\"address\": \"0x11\"",
+                DecodeError::Invalid(ProofError::MisalignedPage { address: 0x11 }).to_string(),
+            ),
+            (
+                "\"address\": \"0x80\"",
+                "\"address\": \"0x100\"",
+                DecodeError::Invalid(ProofError::PageOutOfRange { address: 0x100 }).to_string(),
+            ),
+            (
+                "\"address\": \"0x80\"",
+                "\"address\": \"0x10\"",
+                DecodeError::Invalid(ProofError::OverlappingPages { address: 0x10 }).to_string(),
+            ),
+            (
+                "\"address_low\": \"0x40\"",
+                "\"address_low\": \"0x30\"",
+                DecodeError::Invalid(ProofError::MisalignedEntry {
+                    address_low: 0x30,
+                    address_high: 0x7f,
+                })
+                .to_string(),
+            ),
+            (
+                "\"address_high\": \"0xff\"",
+                "\"address_high\": \"0x1ff\"",
+                DecodeError::Invalid(ProofError::EntryOutOfRange {
+                    address_low: 0xc0,
+                    address_high: 0x1ff,
+                })
+                .to_string(),
+            ),
+            (
+                "\"address_low\": \"0x40\"",
+                "\"address_low\": \"0x90\"",
+                ProofError::InvalidEntryRange {
+                    address_low: 0x90,
+                    address_high: 0x7f,
+                }
+                .to_string(),
+            ),
+            (
+                "\"address\": \"0x10\"",
+                "\"address\": \"16\"",
+                "not a 0x-prefixed hex string: \"16\"".to_string(),
+            ),
+            (
+                "\"data\": \"0x10",
+                "\"data\": \"0x1",
+                "odd number of hex digits".to_string(),
+            ),
+            ("keccak256", "md5", "unknown variant `md5`".to_string()),
+        ] {
+            let corrupt = json.replacen(from, to, 1);
+            let result = serde_json::from_str::<ProofBundle>(&corrupt);
+            assert!(
+                result
+                    .as_ref()
+                    .is_err_and(|result| result.to_string().starts_with(&error)),
+                "{} -> {}: {:?}",
+                from,
+                to,
+                result.err()
+            );
+        }
+        assert!(serde_json::from_str::<ProofBundle>("{}").is_err());
+    }
+}
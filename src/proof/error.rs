@@ -0,0 +1,291 @@
+use crate::proof::types::{PageAddress, ProofHash};
+use std::fmt;
+
+/// Errors that may occur while calculating the Merkle tree root from a page cache and a
+/// multiproof.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofError {
+    /// Neither the page cache nor the multiproof provide the hash of the node at `level`
+    /// (0 being the level of the pages) covering `address_low..=address_high`.
+    MissingNode {
+        level: usize,
+        address_low: PageAddress,
+        address_high: PageAddress,
+    },
+    /// The hash of the node at `level` covering `address_low..=address_high` was supplied, but
+    /// the hash of its parent was supplied as well, so the node was not used to calculate the
+    /// root.
+    RedundantNode {
+        level: usize,
+        address_low: PageAddress,
+        address_high: PageAddress,
+    },
+    /// Multiproof entries that were not used to calculate the root. `address_low` and
+    /// `address_high` describe the first of them.
+    UnusedMultiproofEntries {
+        count: usize,
+        address_low: PageAddress,
+        address_high: PageAddress,
+    },
+    /// Pages that were not used to calculate the root. `address` is the address of the first
+    /// of them.
+    UnusedPages { count: usize, address: PageAddress },
+    /// More than one page was supplied for `address`.
+    OverlappingPages { address: PageAddress },
+    /// The page at `address` is not in the page cache.
+    MissingPage { address: PageAddress },
+    /// The multiproof entry for `address_low..=address_high` was supplied more than once.
+    DuplicateMultiproofEntry {
+        address_low: PageAddress,
+        address_high: PageAddress,
+    },
+    /// Multiproof entries with different hashes were supplied for `address_low..=address_high`.
+    ConflictingMultiproofEntries {
+        address_low: PageAddress,
+        address_high: PageAddress,
+    },
+    /// The multiproof entry range ends before it starts.
+    InvalidEntryRange {
+        address_low: PageAddress,
+        address_high: PageAddress,
+    },
+    /// The multiproof entry range is not the range of a power-of-two subtree of the memory
+    /// chunk, or doesn't follow the convention for the page entries.
+    MisalignedEntry {
+        address_low: PageAddress,
+        address_high: PageAddress,
+    },
+    /// The multiproof entry range is beyond the memory chunk.
+    EntryOutOfRange {
+        address_low: PageAddress,
+        address_high: PageAddress,
+    },
+    /// The page address is not a multiple of the page size.
+    MisalignedPage { address: PageAddress },
+    /// The size of the page data does not match the page size of the memory layout.
+    InvalidPageSize { address: PageAddress, size: usize },
+    /// The page address is beyond the memory chunk.
+    PageOutOfRange { address: PageAddress },
+    /// The number of sibling hashes of a leaf proof does not match the depth of the tree.
+    InvalidSiblingCount { expected: usize, count: usize },
+    /// The memory image is larger than the memory chunk of the memory layout.
+    InvalidImageSize { size: usize },
+    /// The page is larger than the memory chunk, or either of them doesn't fit into the 64-bit
+    /// address space.
+    InvalidLayout {
+        memory_log2_size: usize,
+        page_log2_size: usize,
+    },
+    /// The calculated root does not match the expected one.
+    RootMismatch {
+        expected: ProofHash,
+        computed: ProofHash,
+    },
+}
+
+fn write_hash(f: &mut fmt::Formatter<'_>, hash: &ProofHash) -> fmt::Result {
+    write!(f, "0x")?;
+    for byte in hash {
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProofError::MissingNode {
+                level,
+                address_low,
+                address_high,
+            } => write!(
+                f,
+                "missing node at level {}: {:x} - {:x}",
+                level, address_low, address_high
+            ),
+            ProofError::RedundantNode {
+                level,
+                address_low,
+                address_high,
+            } => write!(
+                f,
+                "redundant node at level {}: {:x} - {:x}",
+                level, address_low, address_high
+            ),
+            ProofError::UnusedMultiproofEntries {
+                count,
+                address_low,
+                address_high,
+            } => write!(
+                f,
+                "{} unused multiproof entries, first: {:x} - {:x}",
+                count, address_low, address_high
+            ),
+            ProofError::UnusedPages { count, address } => write!(
+                f,
+                "{} unused pages, first page address: {:x}",
+                count, address
+            ),
+            ProofError::OverlappingPages { address } => {
+                write!(f, "more than one page at address: {:x}", address)
+            }
+            ProofError::MissingPage { address } => {
+                write!(f, "missing page at address: {:x}", address)
+            }
+            ProofError::DuplicateMultiproofEntry {
+                address_low,
+                address_high,
+            } => write!(
+                f,
+                "duplicate multiproof entry: {:x} - {:x}",
+                address_low, address_high
+            ),
+            ProofError::ConflictingMultiproofEntries {
+                address_low,
+                address_high,
+            } => write!(
+                f,
+                "conflicting multiproof entries: {:x} - {:x}",
+                address_low, address_high
+            ),
+            ProofError::InvalidEntryRange {
+                address_low,
+                address_high,
+            } => write!(
+                f,
+                "invalid multiproof entry range: {:x} - {:x}",
+                address_low, address_high
+            ),
+            ProofError::MisalignedEntry {
+                address_low,
+                address_high,
+            } => write!(
+                f,
+                "misaligned multiproof entry: {:x} - {:x}",
+                address_low, address_high
+            ),
+            ProofError::EntryOutOfRange {
+                address_low,
+                address_high,
+            } => write!(
+                f,
+                "multiproof entry out of range: {:x} - {:x}",
+                address_low, address_high
+            ),
+            ProofError::MisalignedPage { address } => {
+                write!(f, "misaligned page address: {:x}", address)
+            }
+            ProofError::InvalidPageSize { address, size } => {
+                write!(f, "invalid size of page {:x}: {} bytes", address, size)
+            }
+            ProofError::PageOutOfRange { address } => {
+                write!(f, "page address out of range: {:x}", address)
+            }
+            ProofError::InvalidSiblingCount { expected, count } => write!(
+                f,
+                "invalid number of sibling hashes: {}, expected: {}",
+                count, expected
+            ),
+            ProofError::InvalidImageSize { size } => {
+                write!(f, "invalid size of memory image: {} bytes", size)
+            }
+            ProofError::InvalidLayout {
+                memory_log2_size,
+                page_log2_size,
+            } => write!(
+                f,
+                "invalid memory layout: memory log2 size {}, page log2 size {}",
+                memory_log2_size, page_log2_size
+            ),
+            ProofError::RootMismatch { expected, computed } => {
+                write!(f, "root mismatch, expected: ")?;
+                write_hash(f, expected)?;
+                write!(f, ", computed: ")?;
+                write_hash(f, computed)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProofError {}
+
+/// The access at `index` of an access log failed to verify.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessError {
+    pub index: usize,
+    pub error: ProofError,
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "access {} failed: {}", self.index, self.error)
+    }
+}
+
+impl std::error::Error for AccessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Errors that may occur while decoding the serialized proofs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ended before the value was decoded.
+    UnexpectedEnd,
+    /// The input has bytes left after the value was decoded.
+    TrailingBytes {
+        count: usize,
+    },
+    /// The input doesn't start with the proof bundle magic bytes.
+    InvalidMagic,
+    UnsupportedVersion {
+        version: u8,
+    },
+    UnknownHashAlgorithm {
+        id: u8,
+    },
+    InvalidLayout {
+        memory_log2_size: u8,
+        page_log2_size: u8,
+    },
+    /// The decoded pages or multiproof entries are invalid, e.g. a page is misaligned or two
+    /// entries have the same range.
+    Invalid(ProofError),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeError::TrailingBytes { count } => {
+                write!(f, "{} trailing bytes after the end of input", count)
+            }
+            DecodeError::InvalidMagic => write!(f, "not a proof bundle"),
+            DecodeError::UnsupportedVersion { version } => {
+                write!(f, "unsupported proof bundle version: {}", version)
+            }
+            DecodeError::UnknownHashAlgorithm { id } => {
+                write!(f, "unknown hash algorithm id: {}", id)
+            }
+            DecodeError::InvalidLayout {
+                memory_log2_size,
+                page_log2_size,
+            } => write!(
+                f,
+                "invalid memory layout: memory log2 size {}, page log2 size {}",
+                memory_log2_size, page_log2_size
+            ),
+            DecodeError::Invalid(error) => write!(f, "invalid proof data: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodeError::Invalid(error) => Some(error),
+            _ => None,
+        }
+    }
+}
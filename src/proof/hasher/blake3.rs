@@ -0,0 +1,23 @@
+use crate::proof::{
+    hasher::{HashAlgorithm, MerkleHasher},
+    types::ProofHash,
+};
+
+/// BLAKE3 with the default 32-byte output, available with the `blake3` feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Blake3;
+
+impl MerkleHasher for Blake3 {
+    const ALGORITHM: HashAlgorithm = HashAlgorithm::Blake3;
+
+    fn hash_leaf(data: &[u8]) -> ProofHash {
+        ::blake3::hash(data).into()
+    }
+
+    fn hash_node(left: &ProofHash, right: &ProofHash) -> ProofHash {
+        let mut hasher = ::blake3::Hasher::new();
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().into()
+    }
+}
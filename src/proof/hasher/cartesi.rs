@@ -0,0 +1,100 @@
+use crate::proof::hasher::{Keccak256, WordTree};
+
+/// Keccak-256 over the Cartesi machine Merkle tree, whose leaves are 8-byte words rather than
+/// pages. A page is hashed as the root of the subtree of its words, so the roots don't depend on
+/// the page size, e.g. with `MemoryLayout::CARTESI`. The words and the nodes are hashed as with
+/// `Keccak256`. The roots are checked against an independent implementation of the tree,
+/// `testdata/cartesi_vectors.py`, but not yet against the roots of a cartesi-machine run.
+pub type Cartesi = WordTree<Keccak256, 3>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::{
+        hasher::{MerkleHasher, WordHasher},
+        merkle_tree::MerkleTree,
+        pristine::PristineHashes,
+        types::{MemoryLayout, PageAddress},
+    };
+    use serde_json::Value;
+    use std::collections::BTreeMap;
+
+    /// Golden vectors generated by `testdata/cartesi_vectors.py`.
+    const VECTORS: &str = include_str!("../../../testdata/cartesi_vectors.json");
+
+    fn hex(value: &Value) -> Vec<u8> {
+        let digits = value.as_str().unwrap().strip_prefix("0x").unwrap();
+        (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test_log::test]
+    fn test_pristine() {
+        let vectors = serde_json::from_str::<Value>(VECTORS).unwrap();
+        let layout = MemoryLayout::CARTESI;
+        let pristine = PristineHashes::<Cartesi>::new(layout);
+        let expected = vectors["pristine"].as_array().unwrap();
+        assert_eq!(expected.len(), 64 - Cartesi::WORD_LOG2_SIZE + 1);
+        for (i, expected) in expected.iter().enumerate() {
+            let log2_size = Cartesi::WORD_LOG2_SIZE + i;
+            let hash = if log2_size < layout.page_log2_size() {
+                Cartesi::hash_leaf(&vec![0u8; 1 << log2_size])
+            } else {
+                pristine.get(log2_size - layout.page_log2_size())
+            };
+            assert_eq!(hash.to_vec(), hex(expected), "log2 size {}", log2_size);
+        }
+    }
+
+    #[test_log::test]
+    fn test_golden_vectors() {
+        let vectors = serde_json::from_str::<Value>(VECTORS).unwrap();
+        for memory in vectors["memories"].as_array().unwrap() {
+            let name = &memory["name"];
+            let words: Vec<(PageAddress, Vec<u8>)> = memory["words"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|word| {
+                    let address = word["address"]
+                        .as_str()
+                        .unwrap()
+                        .strip_prefix("0x")
+                        .unwrap();
+                    let address = PageAddress::from_str_radix(address, 16).unwrap();
+                    let mut value = hex(&word["value"]);
+                    // the machine stores the words little-endian
+                    value.reverse();
+                    (address, value)
+                })
+                .collect();
+            let expected = hex(&memory["root"]);
+
+            // the same root with the pages of the machine and with the words as pages
+            for page_log2_size in [12, Cartesi::WORD_LOG2_SIZE] {
+                let layout = MemoryLayout::new(64, page_log2_size);
+                let mut pages = BTreeMap::new();
+                for (address, value) in &words {
+                    let page_address = address & !(layout.page_size() - 1);
+                    let offset = (address - page_address) as usize;
+                    pages
+                        .entry(page_address)
+                        .or_insert_with(|| vec![0u8; layout.page_size() as usize])
+                        [offset..offset + value.len()]
+                        .copy_from_slice(value);
+                }
+                let mut tree = MerkleTree::<Cartesi>::with_hasher(layout);
+                tree.update_pages(pages).unwrap();
+                assert_eq!(
+                    tree.root().to_vec(),
+                    expected,
+                    "{:?}, page log2 size {}",
+                    name,
+                    page_log2_size
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use crate::proof::{
+    hasher::{HashAlgorithm, MerkleHasher},
+    types::{ProofHash, HASH_SIZE},
+};
+use tiny_keccak::{Hasher, Keccak};
+
+/// Keccak-256, the default hash function of the Merkle tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Keccak256;
+
+impl MerkleHasher for Keccak256 {
+    const ALGORITHM: HashAlgorithm = HashAlgorithm::Keccak256;
+
+    fn hash_leaf(data: &[u8]) -> ProofHash {
+        let mut hasher = Keccak::v256();
+        let mut output = [0u8; HASH_SIZE];
+        hasher.update(data);
+        hasher.finalize(&mut output);
+        output
+    }
+
+    fn hash_node(left: &ProofHash, right: &ProofHash) -> ProofHash {
+        let mut hasher = Keccak::v256();
+        let mut output = [0u8; HASH_SIZE];
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize(&mut output);
+        output
+    }
+}
@@ -0,0 +1,152 @@
+#[cfg(feature = "blake3")]
+mod blake3;
+mod cartesi;
+mod keccak256;
+#[cfg(feature = "sha2")]
+mod sha256;
+mod word_tree;
+
+#[cfg(feature = "blake3")]
+pub use self::blake3::Blake3;
+pub use self::cartesi::Cartesi;
+pub use self::keccak256::Keccak256;
+#[cfg(feature = "sha2")]
+pub use self::sha256::Sha256;
+pub use self::word_tree::WordTree;
+
+use crate::proof::types::ProofHash;
+
+/// Identifiers of the supported hash functions, as they are encoded in the serialized proofs.
+/// SHA-256 and BLAKE3 proofs are decoded whatever the features are, but calculating their roots
+/// needs the `sha2` and `blake3` features respectively. With the `serde` feature they are
+/// serialized by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum HashAlgorithm {
+    Keccak256 = 1,
+    Sha256 = 2,
+    Blake3 = 3,
+    Cartesi = 4,
+}
+
+impl HashAlgorithm {
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(HashAlgorithm::Keccak256),
+            2 => Some(HashAlgorithm::Sha256),
+            3 => Some(HashAlgorithm::Blake3),
+            4 => Some(HashAlgorithm::Cartesi),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "keccak256" => Some(HashAlgorithm::Keccak256),
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            "cartesi" => Some(HashAlgorithm::Cartesi),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Keccak256 => "keccak256",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Cartesi => "cartesi",
+        }
+    }
+}
+
+/// Hash function used to build the Merkle tree. Leaves (pages) and internal nodes are hashed
+/// separately, so that an implementation may domain-separate them.
+pub trait MerkleHasher: Send + Sync {
+    /// Identifier of the hash function.
+    const ALGORITHM: HashAlgorithm;
+
+    /// Calculates the hash of the page data.
+    fn hash_leaf(data: &[u8]) -> ProofHash;
+
+    /// Calculates the hash of an internal node from the hashes of its children.
+    fn hash_node(left: &ProofHash, right: &ProofHash) -> ProofHash;
+}
+
+/// Leaf strategy hashing a page as the root of the Merkle subtree of its fixed-size words, rather
+/// than as a whole. This way a single word can be proven without shipping its page, see
+/// `word_proof`. `WordTree` implements it over any hash function, returning `hash_words` from
+/// `hash_leaf`.
+pub trait WordHasher: MerkleHasher {
+    /// Log2 size of the words, the leaves of the subtree of a page.
+    const WORD_LOG2_SIZE: usize;
+
+    /// Calculates the hash of a word.
+    fn hash_word(word: &[u8]) -> ProofHash;
+
+    /// Calculates the root of the subtree of the words of `data`, which is a power-of-two number
+    /// of words. Data of a word or less is hashed as a single word.
+    fn hash_words(data: &[u8]) -> ProofHash {
+        if data.len() <= 1 << Self::WORD_LOG2_SIZE {
+            return Self::hash_word(data);
+        }
+        let (left, right) = data.split_at(data.len() / 2);
+        Self::hash_node(&Self::hash_words(left), &Self::hash_words(right))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_hex(hash: ProofHash) -> String {
+        hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test_log::test]
+    fn test_keccak256() {
+        assert_eq!(
+            to_hex(Keccak256::hash_leaf(b"")),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test_log::test]
+    fn test_sha256() {
+        assert_eq!(
+            to_hex(Sha256::hash_leaf(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test_log::test]
+    fn test_blake3() {
+        assert_eq!(
+            to_hex(Blake3::hash_leaf(b"abc")),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+    }
+
+    #[test_log::test]
+    fn test_hash_node() {
+        let left = [1u8; 32];
+        let right = [2u8; 32];
+        let mut data = left.to_vec();
+        data.extend_from_slice(&right);
+        assert_eq!(
+            Keccak256::hash_node(&left, &right),
+            Keccak256::hash_leaf(&data)
+        );
+        #[cfg(feature = "sha2")]
+        assert_eq!(Sha256::hash_node(&left, &right), Sha256::hash_leaf(&data));
+        #[cfg(feature = "blake3")]
+        assert_eq!(Blake3::hash_node(&left, &right), Blake3::hash_leaf(&data));
+    }
+}
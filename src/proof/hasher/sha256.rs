@@ -0,0 +1,24 @@
+use crate::proof::{
+    hasher::{HashAlgorithm, MerkleHasher},
+    types::ProofHash,
+};
+use sha2::Digest;
+
+/// SHA-256 (FIPS 180-4), available with the `sha2` feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sha256;
+
+impl MerkleHasher for Sha256 {
+    const ALGORITHM: HashAlgorithm = HashAlgorithm::Sha256;
+
+    fn hash_leaf(data: &[u8]) -> ProofHash {
+        sha2::Sha256::digest(data).into()
+    }
+
+    fn hash_node(left: &ProofHash, right: &ProofHash) -> ProofHash {
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().into()
+    }
+}
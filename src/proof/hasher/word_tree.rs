@@ -0,0 +1,91 @@
+use crate::proof::{
+    hasher::{HashAlgorithm, MerkleHasher, WordHasher},
+    types::ProofHash,
+};
+use std::marker::PhantomData;
+
+/// Hash function `H` over the words of `2^WORD_LOG2_SIZE` bytes: a page is hashed as the root of
+/// the subtree of its words, a word with `H::hash_leaf` and a node with `H::hash_node`. The
+/// roots don't depend on the page size, and single words can be proven, see `word_proof`.
+///
+/// Only `Cartesi`, Keccak-256 over 8-byte words, has an identifier in the serialized proofs, so
+/// `ALGORITHM` fails to compile for any other instance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WordTree<H, const WORD_LOG2_SIZE: usize>(PhantomData<H>);
+
+impl<H: MerkleHasher, const WORD_LOG2_SIZE: usize> MerkleHasher for WordTree<H, WORD_LOG2_SIZE> {
+    const ALGORITHM: HashAlgorithm = match (H::ALGORITHM, WORD_LOG2_SIZE) {
+        (HashAlgorithm::Keccak256, 3) => HashAlgorithm::Cartesi,
+        _ => panic!("the word tree has no hash algorithm identifier"),
+    };
+
+    fn hash_leaf(data: &[u8]) -> ProofHash {
+        Self::hash_words(data)
+    }
+
+    fn hash_node(left: &ProofHash, right: &ProofHash) -> ProofHash {
+        H::hash_node(left, right)
+    }
+}
+
+impl<H: MerkleHasher, const WORD_LOG2_SIZE: usize> WordHasher for WordTree<H, WORD_LOG2_SIZE> {
+    const WORD_LOG2_SIZE: usize = WORD_LOG2_SIZE;
+
+    fn hash_word(word: &[u8]) -> ProofHash {
+        H::hash_leaf(word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::{
+        hasher::{Cartesi, Keccak256},
+        merkle_tree::MerkleTree,
+        types::MemoryLayout,
+        word_proof::word_layout,
+    };
+
+    /// The root of a memory must be the same with its pages and with its words as pages.
+    fn check_word_tree<H: MerkleHasher>() {
+        let data: Vec<u8> = (0..64).collect();
+        let words = |data: &[u8]| {
+            let (left, right) = data.split_at(data.len() / 2);
+            H::hash_node(&H::hash_leaf(left), &H::hash_leaf(right))
+        };
+        assert_eq!(
+            WordTree::<H, 4>::hash_leaf(&data),
+            H::hash_node(&words(&data[..32]), &words(&data[32..]))
+        );
+        assert_eq!(
+            WordTree::<H, 4>::hash_leaf(&data[..16]),
+            H::hash_leaf(&data[..16])
+        );
+
+        let layout = MemoryLayout::new(10, 7);
+        let mut page_tree = MerkleTree::<WordTree<H, 4>>::with_hasher(layout);
+        let page: Vec<u8> = (0..128).collect();
+        page_tree.update_page(0x80, page.clone()).unwrap();
+        let word_layout = word_layout::<WordTree<H, 4>>(&layout).unwrap();
+        let mut word_tree = MerkleTree::<WordTree<H, 4>>::with_hasher(word_layout);
+        word_tree
+            .update_pages(
+                page.chunks(16)
+                    .enumerate()
+                    .map(|(i, word)| (0x80 + 16 * i as u64, word.to_vec())),
+            )
+            .unwrap();
+        assert_eq!(page_tree.root(), word_tree.root());
+    }
+
+    #[test_log::test]
+    fn test_word_tree() {
+        assert_eq!(Cartesi::ALGORITHM, HashAlgorithm::Cartesi);
+        assert_eq!(Cartesi::WORD_LOG2_SIZE, 3);
+        check_word_tree::<Keccak256>();
+        #[cfg(feature = "sha2")]
+        check_word_tree::<crate::proof::hasher::Sha256>();
+        #[cfg(feature = "blake3")]
+        check_word_tree::<crate::proof::hasher::Blake3>();
+    }
+}
@@ -0,0 +1,91 @@
+//! Helpers for `#[serde(with = ...)]` that write the hashes, the page data and the addresses as
+//! `0x`-prefixed hex strings, so that the proofs are readable in JSON.
+
+use serde::{de::Error, Deserialize, Deserializer, Serializer};
+use std::fmt::Write;
+
+fn to_hex(data: &[u8]) -> String {
+    let mut hex = String::with_capacity(2 + data.len() * 2);
+    hex.push_str("0x");
+    for byte in data {
+        write!(hex, "{:02x}", byte).unwrap();
+    }
+    hex
+}
+
+/// Reads the `0x`-prefixed hex digits of a string.
+fn hex_digits<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    match value.strip_prefix("0x") {
+        Some(digits) if digits.bytes().all(|digit| digit.is_ascii_hexdigit()) => {
+            Ok(digits.to_string())
+        }
+        _ => Err(D::Error::custom(format!(
+            "not a 0x-prefixed hex string: {:?}",
+            value
+        ))),
+    }
+}
+
+fn from_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let digits = hex_digits(deserializer)?;
+    if !digits.len().is_multiple_of(2) {
+        return Err(D::Error::custom("odd number of hex digits"));
+    }
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+/// Variable-size data, such as the contents of a page.
+pub mod bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&to_hex(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        from_hex(deserializer)
+    }
+}
+
+/// Fixed-size data, such as a hash.
+pub mod array {
+    use super::*;
+
+    pub fn serialize<S: Serializer, const N: usize>(
+        data: &[u8; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&to_hex(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[u8; N], D::Error> {
+        from_hex(deserializer)?
+            .try_into()
+            .map_err(|data: Vec<u8>| D::Error::invalid_length(data.len(), &"a hash"))
+    }
+}
+
+/// A memory address, written without leading zeros.
+pub mod address {
+    use super::*;
+    use crate::proof::types::PageAddress;
+
+    pub fn serialize<S: Serializer>(
+        address: &PageAddress,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:#x}", address))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PageAddress, D::Error> {
+        PageAddress::from_str_radix(&hex_digits(deserializer)?, 16).map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,269 @@
+use crate::proof::{
+    error::ProofError,
+    hasher::MerkleHasher,
+    multiproof::{strict_root, KnownNodes, Multiproof, MultiproofEntry},
+    page_cache::{Page, PageCache},
+    types::{MemoryLayout, ProofHash},
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Inclusion proof for a single page: the page and the hashes of the siblings of the nodes on
+/// the path from the page to the root, ordered from the leaf to the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeafProof {
+    pub page: Page,
+    pub siblings: Vec<ProofHash>,
+}
+
+impl LeafProof {
+    /// Reconstructs the Merkle tree root of the memory chunk described by `layout` using the
+    /// hash function `H`.
+    pub fn root<H: MerkleHasher>(&self, layout: &MemoryLayout) -> Result<ProofHash, ProofError> {
+        let address = self.page.address;
+        if !layout.is_page_aligned(address) {
+            return Err(ProofError::MisalignedPage { address });
+        }
+        if address > layout.last_address() {
+            return Err(ProofError::PageOutOfRange { address });
+        }
+        if self.page.data.len() as u64 != layout.page_size() {
+            return Err(ProofError::InvalidPageSize {
+                address,
+                size: self.page.data.len(),
+            });
+        }
+        if self.siblings.len() != layout.depth() {
+            return Err(ProofError::InvalidSiblingCount {
+                expected: layout.depth(),
+                count: self.siblings.len(),
+            });
+        }
+
+        let mut index = address >> layout.page_log2_size();
+        let mut hash = self.page.hash::<H>();
+        for sibling in &self.siblings {
+            hash = if index & 1 == 0 {
+                H::hash_node(&hash, sibling)
+            } else {
+                H::hash_node(sibling, &hash)
+            };
+            index >>= 1;
+        }
+        Ok(hash)
+    }
+
+    /// Verifies the proof against `expected_root`.
+    pub fn verify<H: MerkleHasher>(
+        &self,
+        layout: &MemoryLayout,
+        expected_root: &ProofHash,
+    ) -> Result<(), ProofError> {
+        let computed = self.root::<H>(layout)?;
+        if computed != *expected_root {
+            return Err(ProofError::RootMismatch {
+                expected: *expected_root,
+                computed,
+            });
+        }
+        Ok(())
+    }
+
+    /// Combines the leaf proofs into a page cache and a multiproof. The siblings that can be
+    /// calculated from the pages of the other proofs are left out, so the multiproof is
+    /// minimal. All the proofs must lead to the same root.
+    pub fn to_multiproof<H: MerkleHasher>(
+        layout: &MemoryLayout,
+        proofs: &[LeafProof],
+    ) -> Result<(PageCache, Multiproof), ProofError> {
+        let mut root = None;
+        let mut siblings = BTreeMap::new();
+        for proof in proofs {
+            let computed = proof.root::<H>(layout)?;
+            match root {
+                Some(expected) if expected != computed => {
+                    return Err(ProofError::RootMismatch { expected, computed })
+                }
+                _ => root = Some(computed),
+            }
+            let index = proof.page.address >> layout.page_log2_size();
+            for (level, sibling) in proof.siblings.iter().enumerate() {
+                siblings
+                    .entry((level, (index >> level) ^ 1))
+                    .or_insert(*sibling);
+            }
+        }
+
+        let page_cache = PageCache::try_new(
+            layout,
+            proofs.iter().map(|proof| proof.page.clone()).collect(),
+        )?;
+        let indices: BTreeSet<u64> = page_cache
+            .iter()
+            .map(|page| page.address >> layout.page_log2_size())
+            .collect();
+        // a sibling holding any of the pages is calculated from it
+        let multiproof = Multiproof::try_new(
+            siblings
+                .into_iter()
+                .filter(|((level, index), _)| {
+                    let first = index << level;
+                    indices
+                        .range(first..=first | ((1 << level) - 1))
+                        .next()
+                        .is_none()
+                })
+                .map(|((level, index), hash)| MultiproofEntry::new(layout, level, index, hash)),
+        )?;
+        Ok((page_cache, multiproof))
+    }
+
+    /// Splits the page cache and the multiproof into the leaf proofs of every page, sorted by
+    /// the page address.
+    pub fn from_multiproof<H: MerkleHasher>(
+        layout: &MemoryLayout,
+        page_cache: &PageCache,
+        multiproof: &Multiproof,
+    ) -> Result<Vec<LeafProof>, ProofError> {
+        strict_root::<H>(layout, page_cache, multiproof)?;
+        let nodes = KnownNodes::<H>::new(*layout, page_cache, multiproof);
+        page_cache
+            .iter()
+            .map(|page| {
+                let index = page.address >> layout.page_log2_size();
+                let siblings = (0..layout.depth())
+                    .map(|level| nodes.require(level, (index >> level) ^ 1))
+                    .collect::<Result<_, _>>()?;
+                Ok(LeafProof {
+                    page: page.clone(),
+                    siblings,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::{
+        hasher::{Cartesi, Keccak256},
+        merkle_tree::MerkleTree,
+        prover::Prover,
+        types::{PageAddress, HASH_SIZE},
+    };
+
+    fn test_tree(layout: MemoryLayout) -> MerkleTree<Keccak256> {
+        let mut tree = MerkleTree::with_hasher(layout);
+        tree.update_pages(
+            [0x0, 0x40, 0x50, 0x1f0, 0x3f0]
+                .into_iter()
+                .map(|address: PageAddress| (address, vec![(address >> 4) as u8 + 1; 16])),
+        )
+        .unwrap();
+        tree
+    }
+
+    #[test_log::test]
+    fn test_leaf_proof() {
+        let layout = MemoryLayout::new(10, 4);
+        let tree = test_tree(layout);
+        for address in [0x0, 0x50, 0x60, 0x3f0] {
+            let proof = tree.leaf_proof(address).unwrap();
+            assert_eq!(proof.siblings.len(), layout.depth());
+            assert_eq!(proof.root::<Keccak256>(&layout), Ok(tree.root()));
+            assert_eq!(proof.verify::<Keccak256>(&layout, &tree.root()), Ok(()));
+            assert!(proof.verify::<Cartesi>(&layout, &tree.root()).is_err());
+        }
+
+        let mut proof = tree.leaf_proof(0x50).unwrap();
+        proof.siblings[3] = [0u8; HASH_SIZE];
+        assert!(matches!(
+            proof.verify::<Keccak256>(&layout, &tree.root()),
+            Err(ProofError::RootMismatch { .. })
+        ));
+        proof.siblings.pop();
+        assert_eq!(
+            proof.root::<Keccak256>(&layout),
+            Err(ProofError::InvalidSiblingCount {
+                expected: 6,
+                count: 5
+            })
+        );
+        assert_eq!(
+            tree.leaf_proof(0x400).err(),
+            Some(ProofError::PageOutOfRange { address: 0x400 })
+        );
+
+        // a single page memory has no siblings
+        let layout = MemoryLayout::new(4, 4);
+        let mut tree = MerkleTree::<Keccak256>::with_hasher(layout);
+        tree.update_page(0x0, vec![1u8; 16]).unwrap();
+        let proof = tree.leaf_proof(0x0).unwrap();
+        assert!(proof.siblings.is_empty());
+        assert_eq!(proof.root::<Keccak256>(&layout), Ok(tree.root()));
+    }
+
+    #[test_log::test]
+    fn test_multiproof_conversion() {
+        let layout = MemoryLayout::new(10, 4);
+        let tree = test_tree(layout);
+        let mut image = vec![0u8; 1 << 10];
+        for (address, value) in [(0x0, 1), (0x40, 5), (0x50, 6), (0x1f0, 32), (0x3f0, 64)] {
+            image[address..address + 16].fill(value);
+        }
+        let prover = Prover::<Keccak256>::with_hasher(layout, &image).unwrap();
+        assert_eq!(prover.root(), tree.root());
+
+        let addresses = [0x40, 0x50, 0x3f0];
+        let proofs: Vec<LeafProof> = addresses
+            .iter()
+            .map(|address| tree.leaf_proof(*address).unwrap())
+            .collect();
+        let (page_cache, multiproof) =
+            LeafProof::to_multiproof::<Keccak256>(&layout, &proofs).unwrap();
+        assert_eq!(
+            (page_cache.clone(), multiproof.clone()),
+            prover.generate(&addresses).unwrap()
+        );
+
+        let split =
+            LeafProof::from_multiproof::<Keccak256>(&layout, &page_cache, &multiproof).unwrap();
+        assert_eq!(split, proofs);
+
+        // the proofs must agree on the root
+        let mut proofs = proofs;
+        proofs[2].siblings[5] = [0u8; HASH_SIZE];
+        assert!(matches!(
+            LeafProof::to_multiproof::<Keccak256>(&layout, &proofs),
+            Err(ProofError::RootMismatch { .. })
+        ));
+
+        let mut multiproof = multiproof;
+        multiproof.remove(0x3e0, 0x3e0);
+        assert_eq!(
+            LeafProof::from_multiproof::<Keccak256>(&layout, &page_cache, &multiproof).err(),
+            Some(ProofError::MissingNode {
+                level: 0,
+                address_low: 0x3e0,
+                address_high: 0x3ef,
+            })
+        );
+
+        // a forged page shadowed by the root entry is rejected
+        let mut page_cache = page_cache;
+        page_cache.get_mut(0x40).unwrap().data = vec![0xeeu8; 16];
+        let mut multiproof = prover.generate(&addresses).unwrap().1;
+        multiproof
+            .insert(MultiproofEntry::new(
+                &layout,
+                layout.depth(),
+                0,
+                tree.root(),
+            ))
+            .unwrap();
+        assert!(matches!(
+            LeafProof::from_multiproof::<Keccak256>(&layout, &page_cache, &multiproof),
+            Err(ProofError::RedundantNode { .. })
+        ));
+    }
+}
@@ -1,188 +1,1300 @@
 use crate::proof::{
-    multiproof::Multiproof,
+    error::ProofError,
+    hasher::{Keccak256, MerkleHasher},
+    multiproof::{Multiproof, MultiproofEntry},
     page_cache::PageCache,
-    types::{PageAddress, ProofHash, HASH_SIZE, MEMORY_LOG2_SIZE, PAGE_LOG2_SIZE},
+    parallel,
+    pristine::PristineHashes,
+    types::{MemoryLayout, PageAddress, PageData, ProofHash, HASH_SIZE},
 };
-use tiny_keccak::{Hasher, Keccak};
+use std::{borrow::Cow, collections::BTreeSet, iter::Peekable, marker::PhantomData, vec};
+
+/// Where the hash of a node known to `MerkleProof` comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeSource {
+    /// The hash of a page from the page cache.
+    Page,
+    /// A multiproof entry.
+    Multiproof,
+    /// Calculated from the hashes of the children.
+    Computed,
+}
 
 /// Represents a Merkle proof. Based on the given `page_cache` and `multiproof`, calculates the
-/// root of the Merkle tree for the corresponding memory chunk. The memory chunk is divided into
-/// pages, and the Merkle tree `tree` is built from the bottom up. The leaf nodes of the tree
-/// are the hashes of the pages. The internal nodes are the hashes of the concatenation of the
-/// hashes of their children.
+/// root of the Merkle tree for the memory chunk described by `layout`. The memory chunk is
+/// divided into pages, and the Merkle tree is built from the bottom up, one level at a time. The
+/// leaf nodes of the tree are the hashes of the pages. The internal nodes are the hashes of the
+/// concatenation of the hashes of their children.
 /// If a page is missing in the `page_cache`, it is complemented by the corresponding entry from
 /// the `multiproof`.
-pub struct MerkleProof {
-    tree: Vec<Option<ProofHash>>,
-    page_cache: PageCache,
-    multiproof: Multiproof,
+/// Only the known nodes of the current level are kept in `tree`, so the time and memory needed
+/// to calculate the root depend on the amount of the supplied data and the depth of the tree,
+/// not on the size of the memory chunk.
+/// In the strict mode, every page and multiproof entry must be used to calculate the root,
+/// otherwise the proof is rejected.
+/// If `pristine` hashes are set, the nodes that are neither supplied nor calculated are treated
+/// as pristine (zero-filled) subtrees.
+/// If `retain_levels` is set, the known nodes of every level are kept after the root is
+/// calculated, so they can be inspected with `node` and `nodes`.
+/// The `page_cache` and `multiproof` are either owned or borrowed, and are left untouched, so
+/// the root can be calculated any number of times.
+pub struct MerkleProof<'a, H: MerkleHasher = Keccak256> {
+    layout: MemoryLayout,
+    /// Known nodes of the current level as `(index, hash, source)` sorted by index.
+    tree: Vec<(u64, ProofHash, NodeSource)>,
+    /// Known nodes of the levels calculated so far, if they are retained.
+    levels: Option<Vec<Vec<(u64, ProofHash, NodeSource)>>>,
+    /// Current tree level, 0 being the level of the pages.
+    level: usize,
+    page_cache: Cow<'a, PageCache>,
+    multiproof: Cow<'a, Multiproof>,
+    /// Number of pages from the page cache used so far, in the ascending order of addresses.
+    used_pages: usize,
+    /// Ranges of the multiproof entries used so far.
+    used_entries: BTreeSet<(PageAddress, PageAddress)>,
+    /// Nodes missing next to a known sibling as `((level, index), (level, sibling))`, which an
+    /// entry at an upper level may still complement.
+    unresolved: Vec<((usize, u64), (usize, u64))>,
+    strict: bool,
+    pristine: Option<PristineHashes<H>>,
+    hasher: PhantomData<H>,
 }
 
-impl MerkleProof {
-    pub fn new(page_cache: PageCache, multiproof: Multiproof) -> Self {
-        let mut tree: Vec<Option<ProofHash>> = Vec::new();
-        // reserve enough space for the last level of the tree (leaf nodes)
-        tree.reserve(1 << (MEMORY_LOG2_SIZE - PAGE_LOG2_SIZE));
+impl<'a> MerkleProof<'a> {
+    /// Creates a Merkle proof using Keccak-256. The page cache and the multiproof may be passed
+    /// by value or by reference.
+    pub fn new(
+        layout: MemoryLayout,
+        page_cache: impl Into<Cow<'a, PageCache>>,
+        multiproof: impl Into<Cow<'a, Multiproof>>,
+    ) -> Self {
+        Self::with_hasher(layout, page_cache, multiproof)
+    }
+}
+
+impl<'a, H: MerkleHasher> MerkleProof<'a, H> {
+    /// Creates a Merkle proof using the hash function `H`. The page cache and the multiproof may
+    /// be passed by value or by reference.
+    pub fn with_hasher(
+        layout: MemoryLayout,
+        page_cache: impl Into<Cow<'a, PageCache>>,
+        multiproof: impl Into<Cow<'a, Multiproof>>,
+    ) -> Self {
         Self {
-            tree,
-            page_cache,
-            multiproof,
+            layout,
+            tree: Vec::new(),
+            levels: None,
+            level: 0,
+            page_cache: page_cache.into(),
+            multiproof: multiproof.into(),
+            used_pages: 0,
+            used_entries: BTreeSet::new(),
+            unresolved: Vec::new(),
+            strict: false,
+            pristine: None,
+            hasher: PhantomData,
         }
     }
 
-    fn merge_hashes(left: ProofHash, right: ProofHash) -> ProofHash {
-        let mut hasher = Keccak::v256();
-        let mut output = [0u8; HASH_SIZE];
-        hasher.update(&left);
-        hasher.update(&right);
-        hasher.finalize(&mut output);
-        output
+    /// Enables or disables the strict mode. In the strict mode, `calculate_root` fails if any
+    /// page or multiproof entry is not used to calculate the root.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Enables or disables treating the missing nodes as pristine (zero-filled) subtrees. This
+    /// way the multiproof doesn't need to carry the hashes of the pristine subtrees.
+    pub fn pristine(mut self, pristine: bool) -> Self {
+        self.pristine = pristine.then(|| PristineHashes::new(self.layout));
+        self
+    }
+
+    /// Enables or disables keeping the known nodes of every level after the root is calculated.
+    pub fn retain_levels(mut self, retain: bool) -> Self {
+        self.levels = retain.then(Vec::new);
+        self
+    }
+
+    /// Returns the hash of the node at `index` at `level`, 0 being the level of the pages, if
+    /// the levels are retained and the node is known.
+    pub fn node(&self, level: usize, index: u64) -> Option<ProofHash> {
+        let nodes = self.levels.as_ref()?.get(level)?;
+        let position = nodes
+            .binary_search_by_key(&index, |(index, _, _)| *index)
+            .ok()?;
+        Some(nodes[position].1)
+    }
+
+    /// Iterates over the retained known nodes, level by level from the pages up, as
+    /// `(level, (address_low, address_high), hash, source)`.
+    pub fn nodes(
+        &self,
+    ) -> impl Iterator<Item = (usize, (PageAddress, PageAddress), ProofHash, NodeSource)> + '_ {
+        self.levels
+            .iter()
+            .flatten()
+            .enumerate()
+            .flat_map(move |(level, nodes)| {
+                nodes.iter().map(move |(index, hash, source)| {
+                    (level, self.layout.node_range(level, *index), *hash, *source)
+                })
+            })
+    }
+
+    /// Returns the multiproof entries for the nodes of the current level, sorted by index.
+    fn level_entries(&self) -> Peekable<vec::IntoIter<(u64, ProofHash)>> {
+        self.multiproof
+            .level_entries(&self.layout, self.level)
+            .into_iter()
+            .peekable()
+    }
+
+    /// Returns the index of the next entry of the current level that comes after all the nodes
+    /// known so far. The entries for the nodes that are already known are skipped, so they are
+    /// left unused.
+    fn next_entry_index(
+        entries: &mut Peekable<vec::IntoIter<(u64, ProofHash)>>,
+        known: &[(u64, ProofHash, NodeSource)],
+    ) -> Option<u64> {
+        let last = known.last().map(|(last, _, _)| *last);
+        while entries
+            .next_if(|(index, _)| last.is_some_and(|last| *index <= last))
+            .is_some()
+        {}
+        entries.peek().map(|(index, _)| *index)
+    }
+
+    /// Takes the next entry of the current level and marks it as used.
+    fn take_entry(
+        &mut self,
+        entries: &mut Peekable<vec::IntoIter<(u64, ProofHash)>>,
+    ) -> (u64, ProofHash, NodeSource) {
+        let (index, hash) = entries.next().unwrap();
+        let (address_low, address_high) = MultiproofEntry::range(&self.layout, self.level, index);
+        log::debug!(
+            "Reading multiproof entry, address_low: {:x}, address_high: {:x}",
+            address_low,
+            address_high
+        );
+        self.used_entries.insert((address_low, address_high));
+        (index, hash, NodeSource::Multiproof)
+    }
+
+    /// Drops the unresolved nodes below the node at `index` of the current level, whose entry
+    /// complements them. In the strict mode, their known siblings make the entry redundant.
+    fn resolve(&mut self, index: u64) -> Result<(), ProofError> {
+        let level = self.level;
+        let (covered, unresolved): (Vec<_>, Vec<_>) = std::mem::take(&mut self.unresolved)
+            .into_iter()
+            .partition(|((missing_level, missing), _)| missing >> (level - missing_level) == index);
+        self.unresolved = unresolved;
+        match covered.first() {
+            Some((_, (known_level, known))) if self.strict => {
+                let (address_low, address_high) = self.layout.node_range(*known_level, *known);
+                Err(ProofError::RedundantNode {
+                    level: *known_level,
+                    address_low,
+                    address_high,
+                })
+            }
+            _ => Ok(()),
+        }
     }
 
     /// Fills the first level of the tree with the hashes of the pages.
-    fn init(&mut self) {
+    fn init(&mut self) -> Result<(), ProofError> {
         log::debug!(">>> Initializing the tree");
-        let mut page_address: PageAddress = 0;
-        while page_address < (1 << MEMORY_LOG2_SIZE) {
-            if self.page_cache.has_next(page_address) {
-                let page = self.page_cache.get_next().unwrap();
+        let last_index = self.layout.last_index(0);
+        let mut entries = self.level_entries();
+        // the pages are hashed once all of them are checked
+        let mut pages = Vec::new();
+        loop {
+            let page_index = match self.page_cache.iter().nth(self.used_pages) {
+                Some(page) if !self.layout.is_page_aligned(page.address) => {
+                    return Err(ProofError::MisalignedPage {
+                        address: page.address,
+                    })
+                }
+                Some(page) if page.address >> self.layout.page_log2_size() > last_index => {
+                    return Err(ProofError::PageOutOfRange {
+                        address: page.address,
+                    })
+                }
+                Some(page) => Some(page.address >> self.layout.page_log2_size()),
+                None => None,
+            };
+            let entry_index = Self::next_entry_index(&mut entries, &self.tree);
+            let index = match (page_index, entry_index) {
+                (Some(page_index), Some(entry_index)) => page_index.min(entry_index),
+                (Some(index), None) | (None, Some(index)) => index,
+                (None, None) => break,
+            };
+            if page_index == Some(index) {
+                let page = self.page_cache.iter().nth(self.used_pages).unwrap();
+                // pages are sorted, so a page at the index that is already known was supplied
+                // twice
+                if self.tree.last().is_some_and(|(last, _, _)| *last == index) {
+                    return Err(ProofError::OverlappingPages {
+                        address: page.address,
+                    });
+                }
+                if page.data.len() as u64 != self.layout.page_size() {
+                    return Err(ProofError::InvalidPageSize {
+                        address: page.address,
+                        size: page.data.len(),
+                    });
+                }
                 log::debug!("Reading page from cache, page address: {:x}", page.address);
-                self.tree.push(Some(page.hash()));
-            } else if self.multiproof.has_next(page_address, page_address) {
-                let entry = self.multiproof.get_next().unwrap();
-                log::debug!(
-                    "Reading page from multiproof, page address: {:x}",
-                    page_address
-                );
-                self.tree.push(Some(entry.hash));
+                pages.push((self.tree.len(), self.used_pages));
+                self.used_pages += 1;
+                self.tree.push((index, [0u8; HASH_SIZE], NodeSource::Page));
             } else {
-                // if we hit this branch then for some pair of pages should have a multiproof
-                // entry at another tree level
-                log::debug!("No data for page address: {:x}", page_address);
-                self.tree.push(None);
+                let entry = self.take_entry(&mut entries);
+                self.tree.push(entry);
             }
-            page_address += 1 << PAGE_LOG2_SIZE;
         }
+        let page_cache = &*self.page_cache;
+        let hashes = parallel::map(&pages, |(_, page)| {
+            page_cache.iter().nth(*page).unwrap().hash::<H>()
+        });
+        for ((position, _), hash) in pages.iter().zip(hashes) {
+            self.tree[*position].1 = hash;
+        }
+        Ok(())
     }
 
     /// Moves a level up. Bubbles up the hashes from the previous level to the next.
-    fn bubble_up(&mut self) {
-        // the size of the memory chunk that is encoded by an entry at the current merkle tree level
-        let entry_size = (1 << MEMORY_LOG2_SIZE) / (self.tree.len() >> 1);
-        log::debug!(">>> Bubbling up, entry size: {}", entry_size);
-        // we read two child nodes' hashes at a time
-        let read_range = (0..self.tree.len()).step_by(2);
-        // we write one parent node hash in-place
-        let write_range = 0..self.tree.len();
-        // bubble up the hashes
-        for (r, w) in read_range.zip(write_range) {
-            let left = self.tree[r];
-            let right = self.tree[r + 1];
-            if left.is_none() || right.is_none() {
-                // in fact, both should be none. if only one is none, we have an excessive data
-                if self.multiproof.has_next(
-                    (w * entry_size) as u64,
-                    (w * entry_size + entry_size - 1) as u64,
-                ) {
-                    let entry = self.multiproof.get_next().unwrap();
-                    log::debug!(
-                        "Reading multiproof entry, address_low: {:x}, address_high: {:x}",
-                        entry.address_low,
-                        entry.address_high
-                    );
-                    self.tree[w] = Some(entry.hash);
-                } else {
-                    log::debug!(
-                        "No data for node: {:x} - {:x}",
-                        (w * entry_size),
-                        (w * entry_size + entry_size - 1)
-                    );
-                    self.tree[w] = None;
-                }
-            } else {
+    fn bubble_up(&mut self) -> Result<(), ProofError> {
+        let child_level = self.level;
+        self.level += 1;
+        log::debug!(">>> Bubbling up, level: {}", self.level);
+        let mut children = std::mem::take(&mut self.tree).into_iter().peekable();
+        let mut parents: Vec<(u64, ProofHash, NodeSource)> = Vec::new();
+        let mut entries = self.level_entries();
+        // the children are merged once the whole level is checked
+        let mut merges = Vec::new();
+        loop {
+            let child_index = children.peek().map(|(index, _, _)| index >> 1);
+            let entry_index = Self::next_entry_index(&mut entries, &parents);
+            let index = match (child_index, entry_index) {
+                (Some(child_index), Some(entry_index)) => child_index.min(entry_index),
+                (Some(index), None) | (None, Some(index)) => index,
+                (None, None) => break,
+            };
+            let (address_low, address_high) = self.layout.node_range(self.level, index);
+            let left = children.next_if(|(child, _, _)| *child == index << 1);
+            let right = children.next_if(|(child, _, _)| *child == (index << 1) | 1);
+            // an entry only fills a gap, the known children are merged rather than overridden
+            if entry_index == Some(index) && !self.strict && left.is_some() && right.is_some() {
                 log::debug!(
-                    "Merging hashes for node: {:x} - {:x}",
-                    (w * entry_size),
-                    (w * entry_size + entry_size - 1)
+                    "Ignoring redundant multiproof entry, address_low: {:x}, address_high: {:x}",
+                    address_low,
+                    address_high
                 );
-                let merged_hash = MerkleProof::merge_hashes(left.unwrap(), right.unwrap());
-                self.tree[w] = Some(merged_hash);
+                entries.next();
+            } else if entry_index == Some(index) {
+                let entry = self.take_entry(&mut entries);
+                self.resolve(index)?;
+                if self.strict {
+                    // if any of the children is known, we have an excessive data
+                    if let Some((child, _, _)) = left.or(right) {
+                        let (address_low, address_high) =
+                            self.layout.node_range(child_level, child);
+                        return Err(ProofError::RedundantNode {
+                            level: child_level,
+                            address_low,
+                            address_high,
+                        });
+                    }
+                }
+                parents.push(entry);
+                continue;
             }
+            let (left, right) = match (left, right) {
+                (Some((_, left, _)), Some((_, right, _))) => {
+                    log::debug!(
+                        "Merging hashes for node: {:x} - {:x}",
+                        address_low,
+                        address_high
+                    );
+                    (left, right)
+                }
+                (Some((_, left, _)), None) if self.pristine.is_some() => {
+                    (left, self.pristine.as_ref().unwrap().get(child_level))
+                }
+                (None, Some((_, right, _))) if self.pristine.is_some() => {
+                    (self.pristine.as_ref().unwrap().get(child_level), right)
+                }
+                (left, _) => {
+                    // the node is left unknown, an entry at an upper level may still complement
+                    // the missing child
+                    let child = (index << 1) | u64::from(left.is_some());
+                    log::debug!("No data for node: {:x} - {:x}", address_low, address_high);
+                    self.unresolved
+                        .push(((child_level, child), (child_level, child ^ 1)));
+                    continue;
+                }
+            };
+            merges.push((parents.len(), left, right));
+            parents.push((index, [0u8; HASH_SIZE], NodeSource::Computed));
+        }
+        let hashes = parallel::map(&merges, |(_, left, right)| H::hash_node(left, right));
+        for ((position, _, _), hash) in merges.iter().zip(hashes) {
+            parents[*position].1 = hash;
+        }
+        self.tree = parents;
+        Ok(())
+    }
+
+    /// Keeps the known nodes of the current level if the levels are retained.
+    fn retain_level(&mut self) {
+        if let Some(levels) = &mut self.levels {
+            levels.push(self.tree.clone());
+        }
+    }
+
+    /// Forgets the nodes and the used inputs of the previous calculation.
+    fn reset(&mut self) {
+        self.tree.clear();
+        self.level = 0;
+        self.used_pages = 0;
+        self.used_entries.clear();
+        self.unresolved.clear();
+        if let Some(levels) = &mut self.levels {
+            levels.clear();
+        }
+    }
+
+    /// Checks that all the pages and multiproof entries were consumed.
+    fn check_consumed(&self) -> Result<(), ProofError> {
+        // the pages are used in order, so the unused ones come last
+        if let Some(page) = self.page_cache.iter().nth(self.used_pages) {
+            return Err(ProofError::UnusedPages {
+                count: self.page_cache.len() - self.used_pages,
+                address: page.address,
+            });
+        }
+        let mut unused = self.multiproof.iter().filter(|entry| {
+            !self
+                .used_entries
+                .contains(&(entry.address_low, entry.address_high))
+        });
+        if let Some(entry) = unused.next() {
+            return Err(ProofError::UnusedMultiproofEntries {
+                count: self.multiproof.len() - self.used_entries.len(),
+                address_low: entry.address_low,
+                address_high: entry.address_high,
+            });
         }
-        self.tree.truncate(self.tree.len() >> 1);
+        Ok(())
     }
 
     /// Calculates the Merkle tree root which is a final proof.
-    /// Returns `None` if the data provided in `page_cache` and `multiproof` is incomplete.
-    pub fn calculate_root(&mut self) -> Result<ProofHash, ()> {
-        self.init();
-        while self.tree.len() > 1 {
-            self.bubble_up();
+    /// Returns an error if the data provided in `page_cache` and `multiproof` is incomplete or
+    /// malformed. The inputs are left untouched, so repeated calls give the same result.
+    pub fn calculate_root(&mut self) -> Result<ProofHash, ProofError> {
+        self.reset();
+        self.multiproof.validate(&self.layout)?;
+        self.init()?;
+        self.retain_level();
+        while self.level < self.layout.depth() {
+            self.bubble_up()?;
+            self.retain_level();
         }
-        match self.tree[0] {
-            Some(hash) => Ok(hash),
-            // None means that at some point there was not enough data provided (in the page cache
-            // or in the multiproof) to calculate the hash. This None was bubbled up to the root.
-            None => Err(()),
+        if self.strict {
+            self.check_consumed()?;
         }
+        match self.tree.first() {
+            Some((_, hash, _)) => Ok(*hash),
+            None if self.pristine.is_some() => {
+                Ok(self.pristine.as_ref().unwrap().get(self.layout.depth()))
+            }
+            // no entry complemented the first missing node
+            None if !self.unresolved.is_empty() => {
+                let ((level, index), _) = self.unresolved[0];
+                let (address_low, address_high) = self.layout.node_range(level, index);
+                Err(ProofError::MissingNode {
+                    level,
+                    address_low,
+                    address_high,
+                })
+            }
+            // no data was provided (in the page cache or in the multiproof) for the whole memory
+            // chunk
+            None => Err(ProofError::MissingNode {
+                level: self.layout.depth(),
+                address_low: 0,
+                address_high: self.layout.last_address(),
+            }),
+        }
+    }
+
+    /// Calculates the root in the strict mode, keeping the mode set with `strict` for the later
+    /// calls of `calculate_root`.
+    fn calculate_strict_root(&mut self) -> Result<ProofHash, ProofError> {
+        let strict = std::mem::replace(&mut self.strict, true);
+        let root = self.calculate_root();
+        self.strict = strict;
+        root
+    }
+
+    /// Verifies the proof against `expected_root`. The proof is checked in the strict mode, so
+    /// every page and multiproof entry must be used to calculate the root. The mode set with
+    /// `strict` is kept for the later calls of `calculate_root`.
+    pub fn verify(&mut self, expected_root: &ProofHash) -> Result<(), ProofError> {
+        let computed = self.calculate_strict_root()?;
+        if computed != *expected_root {
+            return Err(ProofError::RootMismatch {
+                expected: *expected_root,
+                computed,
+            });
+        }
+        Ok(())
+    }
+
+    /// Writes the given pages and returns the roots before and after the writes. The new root
+    /// is calculated from the same multiproof, so every written page must be in the page cache.
+    /// The roots are calculated in the strict mode, as with `verify`, so that no multiproof
+    /// entry shadows a written page. Afterwards the page cache holds the written data, so the
+    /// proof describes the new state. Nothing is written if the proof or any of the writes is
+    /// invalid.
+    pub fn apply_writes<I>(&mut self, writes: I) -> Result<(ProofHash, ProofHash), ProofError>
+    where
+        I: IntoIterator<Item = (PageAddress, PageData)>,
+    {
+        let writes: Vec<(PageAddress, PageData)> = writes.into_iter().collect();
+        for (address, data) in &writes {
+            let address = *address;
+            if !self.layout.is_page_aligned(address) {
+                return Err(ProofError::MisalignedPage { address });
+            }
+            if address > self.layout.last_address() {
+                return Err(ProofError::PageOutOfRange { address });
+            }
+            if data.len() as u64 != self.layout.page_size() {
+                return Err(ProofError::InvalidPageSize {
+                    address,
+                    size: data.len(),
+                });
+            }
+            if self.page_cache.get(address).is_none() {
+                return Err(ProofError::MissingPage { address });
+            }
+        }
+        let old_root = self.calculate_strict_root()?;
+
+        let page_cache = self.page_cache.to_mut();
+        for (address, data) in writes {
+            page_cache.get_mut(address).unwrap().data = data;
+        }
+        let new_root = self.calculate_strict_root()?;
+        Ok((old_root, new_root))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::proof::{multiproof::MultiproofEntry, page_cache::Page, types::HASH_SIZE};
+    use crate::proof::{
+        hasher::Cartesi, merkle_tree::MerkleTree, page_cache::Page, prover::Prover,
+        stream::root_from_reader, types::PAGE_LOG2_SIZE,
+    };
 
-    #[test_log::test]
-    fn test_merkle_proof() {
-        const EXPECTED_ROOT_HASH: [u8; HASH_SIZE] = [
-            0xac, 0x22, 0xaa, 0x42, 0x2a, 0x1f, 0x3e, 0x1a, 0x56, 0x36, 0xc4, 0x63, 0x17, 0xd1,
-            0x35, 0xd3, 0x45, 0xae, 0x03, 0xad, 0xdc, 0x64, 0xe6, 0x91, 0x85, 0x9a, 0xe6, 0xe5,
-            0x9b, 0x5a, 0x69, 0xe3,
-        ];
+    const EXPECTED_ROOT_HASH: [u8; HASH_SIZE] = [
+        0xac, 0x22, 0xaa, 0x42, 0x2a, 0x1f, 0x3e, 0x1a, 0x56, 0x36, 0xc4, 0x63, 0x17, 0xd1, 0x35,
+        0xd3, 0x45, 0xae, 0x03, 0xad, 0xdc, 0x64, 0xe6, 0x91, 0x85, 0x9a, 0xe6, 0xe5, 0x9b, 0x5a,
+        0x69, 0xe3,
+    ];
 
-        let page_cache = PageCache::new(vec![
+    fn test_pages() -> Vec<Page> {
+        vec![
             Page {
-                data: [1u8; 1 << PAGE_LOG2_SIZE],
+                data: vec![1u8; 1 << PAGE_LOG2_SIZE],
                 address: 0x4,
             },
             Page {
-                data: [2u8; 1 << PAGE_LOG2_SIZE],
+                data: vec![2u8; 1 << PAGE_LOG2_SIZE],
                 address: 0xc,
             },
             Page {
-                data: [3u8; 1 << PAGE_LOG2_SIZE],
+                data: vec![3u8; 1 << PAGE_LOG2_SIZE],
                 address: 0x14,
             },
+        ]
+    }
+
+    fn test_entries() -> Vec<MultiproofEntry> {
+        vec![
+            MultiproofEntry {
+                address_low: 0x18,
+                address_high: 0x1f,
+                hash: [0xdu8; HASH_SIZE],
+            },
+            MultiproofEntry {
+                address_low: 0x10,
+                address_high: 0x10,
+                hash: [0xcu8; HASH_SIZE],
+            },
+            MultiproofEntry {
+                address_low: 0x8,
+                address_high: 0x8,
+                hash: [0xbu8; HASH_SIZE],
+            },
+            MultiproofEntry {
+                address_low: 0x0,
+                address_high: 0x0,
+                hash: [0xau8; HASH_SIZE],
+            },
+        ]
+    }
+
+    #[test_log::test]
+    fn test_merkle_proof() {
+        let page_cache = PageCache::new(test_pages());
+        let multiproof = Multiproof::try_new(test_entries()).unwrap();
+
+        let mut merkle_proof = MerkleProof::new(MemoryLayout::default(), page_cache, multiproof);
+        let calculated_root = merkle_proof.calculate_root().expect("Invalid input data");
+        assert_eq!(calculated_root, EXPECTED_ROOT_HASH);
+    }
+
+    #[test_log::test]
+    fn test_reuse() {
+        let page_cache = PageCache::new(test_pages());
+        let multiproof = Multiproof::try_new(test_entries()).unwrap();
+
+        // the inputs are borrowed and left untouched
+        let mut merkle_proof = MerkleProof::new(MemoryLayout::default(), &page_cache, &multiproof)
+            .strict(true)
+            .retain_levels(true);
+        for _ in 0..3 {
+            assert_eq!(merkle_proof.calculate_root(), Ok(EXPECTED_ROOT_HASH));
+            assert_eq!(merkle_proof.nodes().count(), 13);
+        }
+        assert_eq!(merkle_proof.verify(&EXPECTED_ROOT_HASH), Ok(()));
+        assert_eq!(page_cache, PageCache::new(test_pages()));
+        assert_eq!(multiproof.len(), 4);
+
+        // a failure is repeated as well
+        let page_cache = PageCache::new(test_pages()[1..].to_vec());
+        let mut merkle_proof = MerkleProof::new(MemoryLayout::default(), &page_cache, &multiproof);
+        for _ in 0..2 {
+            assert_eq!(
+                merkle_proof.calculate_root(),
+                Err(ProofError::MissingNode {
+                    level: 0,
+                    address_low: 0x4,
+                    address_high: 0x7,
+                })
+            );
+        }
+    }
+
+    #[test_log::test]
+    fn test_retain_levels() {
+        let merkle_proof = || {
+            MerkleProof::new(
+                MemoryLayout::default(),
+                PageCache::new(test_pages()),
+                Multiproof::try_new(test_entries()).unwrap(),
+            )
+        };
+        let mut discarding = merkle_proof();
+        assert_eq!(discarding.calculate_root(), Ok(EXPECTED_ROOT_HASH));
+        assert_eq!(discarding.node(3, 0), None);
+        assert_eq!(discarding.nodes().count(), 0);
+
+        let mut merkle_proof = merkle_proof().retain_levels(true);
+        assert_eq!(merkle_proof.calculate_root(), Ok(EXPECTED_ROOT_HASH));
+        let pages = test_pages();
+        assert_eq!(merkle_proof.node(3, 0), Some(EXPECTED_ROOT_HASH));
+        assert_eq!(merkle_proof.node(0, 1), Some(pages[0].hash::<Keccak256>()));
+        assert_eq!(merkle_proof.node(0, 0), Some([0xau8; HASH_SIZE]));
+        assert_eq!(
+            merkle_proof.node(1, 2),
+            Some(Keccak256::hash_node(
+                &[0xcu8; HASH_SIZE],
+                &pages[2].hash::<Keccak256>()
+            ))
+        );
+        // the children of an entry are not known
+        assert_eq!(merkle_proof.node(0, 6), None);
+
+        let nodes: Vec<_> = merkle_proof
+            .nodes()
+            .map(|(level, range, _, source)| (level, range, source))
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                (0, (0x0, 0x3), NodeSource::Multiproof),
+                (0, (0x4, 0x7), NodeSource::Page),
+                (0, (0x8, 0xb), NodeSource::Multiproof),
+                (0, (0xc, 0xf), NodeSource::Page),
+                (0, (0x10, 0x13), NodeSource::Multiproof),
+                (0, (0x14, 0x17), NodeSource::Page),
+                (1, (0x0, 0x7), NodeSource::Computed),
+                (1, (0x8, 0xf), NodeSource::Computed),
+                (1, (0x10, 0x17), NodeSource::Computed),
+                (1, (0x18, 0x1f), NodeSource::Multiproof),
+                (2, (0x0, 0xf), NodeSource::Computed),
+                (2, (0x10, 0x1f), NodeSource::Computed),
+                (3, (0x0, 0x1f), NodeSource::Computed),
+            ]
+        );
+    }
+
+    #[test_log::test]
+    fn test_entry_order() {
+        let layout = MemoryLayout::new(10, 4);
+        let image: Vec<u8> = (0..1 << 10).map(|i| (i * 3) as u8).collect();
+        let prover = Prover::new(layout, &image).unwrap();
+        let (_, multiproof) = prover.generate(&[0x40, 0x50, 0x3f0]).unwrap();
+        let entries: Vec<MultiproofEntry> = multiproof.iter().collect();
+
+        for rotation in 0..entries.len() {
+            let mut entries = entries.clone();
+            entries.rotate_left(rotation);
+            if rotation % 2 == 1 {
+                entries.reverse();
+            }
+            let (page_cache, _) = prover.generate(&[0x40, 0x50, 0x3f0]).unwrap();
+            let mut merkle_proof =
+                MerkleProof::new(layout, page_cache, Multiproof::try_new(entries).unwrap())
+                    .strict(true);
+            assert_eq!(merkle_proof.calculate_root(), Ok(prover.root()));
+        }
+    }
+
+    #[test_log::test]
+    fn test_many_pages() {
+        // enough pages and nodes to be hashed on several threads with the `parallel` feature
+        let layout = MemoryLayout::new(16, 6);
+        let image: Vec<u8> = (0..1u32 << 16).map(|i| (i * 13 + (i >> 8)) as u8).collect();
+        let page_size = layout.page_size() as usize;
+        let mut level: Vec<ProofHash> = image.chunks(page_size).map(Keccak256::hash_leaf).collect();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| Keccak256::hash_node(&pair[0], &pair[1]))
+                .collect();
+        }
+        let expected_root = level[0];
+
+        let pages = image
+            .chunks(page_size)
+            .enumerate()
+            .map(|(index, data)| Page {
+                data: data.to_vec(),
+                address: (index * page_size) as u64,
+            })
+            .collect();
+        let mut merkle_proof =
+            MerkleProof::new(layout, PageCache::new(pages), Multiproof::new()).strict(true);
+        assert_eq!(merkle_proof.calculate_root(), Ok(expected_root));
+        assert_eq!(Prover::new(layout, &image).unwrap().root(), expected_root);
+        let mut tree = MerkleTree::new(layout);
+        tree.update_pages(
+            image
+                .chunks(page_size)
+                .enumerate()
+                .map(|(index, data)| ((index * page_size) as u64, data.to_vec())),
+        )
+        .unwrap();
+        assert_eq!(tree.root(), expected_root);
+    }
+
+    #[test_log::test]
+    fn test_strict_redundant_node() {
+        let pages = || {
+            let mut pages = test_pages();
+            pages.push(Page {
+                data: vec![4u8; 1 << PAGE_LOG2_SIZE],
+                address: 0x1c,
+            });
+            pages
+        };
+
+        // the excessive page is ignored by default
+        let mut merkle_proof = MerkleProof::new(
+            MemoryLayout::default(),
+            PageCache::new(pages()),
+            Multiproof::try_new(test_entries()).unwrap(),
+        );
+        assert_eq!(merkle_proof.calculate_root(), Ok(EXPECTED_ROOT_HASH));
+
+        let mut merkle_proof = MerkleProof::new(
+            MemoryLayout::default(),
+            PageCache::new(pages()),
+            Multiproof::try_new(test_entries()).unwrap(),
+        )
+        .strict(true);
+        assert_eq!(
+            merkle_proof.calculate_root(),
+            Err(ProofError::RedundantNode {
+                level: 0,
+                address_low: 0x1c,
+                address_high: 0x1f,
+            })
+        );
+    }
+
+    #[test_log::test]
+    fn test_redundant_page_below_entry() {
+        let layout = MemoryLayout::new(10, 4);
+        let image: Vec<u8> = (0..1 << 10).map(|i| (i * 3) as u8).collect();
+        let prover = Prover::new(layout, &image).unwrap();
+        let (page_cache, mut multiproof) = prover.generate(&[0x40]).unwrap();
+        let (_, other) = prover.generate(&[0x0]).unwrap();
+        // the siblings of the page at levels 0 and 1 are replaced by the entry of their
+        // grandparent
+        multiproof.remove(0x50, 0x50).unwrap();
+        multiproof.remove(0x60, 0x7f).unwrap();
+        multiproof
+            .insert(MultiproofEntry {
+                address_low: 0x40,
+                address_high: 0x7f,
+                hash: *other.get(0x40, 0x7f).unwrap(),
+            })
+            .unwrap();
+
+        // the page is ignored by default
+        let mut merkle_proof = MerkleProof::new(layout, &page_cache, &multiproof);
+        assert_eq!(merkle_proof.calculate_root(), Ok(prover.root()));
+
+        let mut merkle_proof = MerkleProof::new(layout, &page_cache, &multiproof).strict(true);
+        assert_eq!(
+            merkle_proof.calculate_root(),
+            Err(ProofError::RedundantNode {
+                level: 0,
+                address_low: 0x40,
+                address_high: 0x4f,
+            })
+        );
+
+        // without the entry, the lowest missing node is reported
+        multiproof.remove(0x40, 0x7f).unwrap();
+        let mut merkle_proof = MerkleProof::new(layout, &page_cache, &multiproof);
+        assert_eq!(
+            merkle_proof.calculate_root(),
+            Err(ProofError::MissingNode {
+                level: 0,
+                address_low: 0x50,
+                address_high: 0x5f,
+            })
+        );
+    }
+
+    #[test_log::test]
+    fn test_forged_page_shadowed_by_entry() {
+        let layout = MemoryLayout::new(10, 4);
+        let image: Vec<u8> = (0..1 << 10).map(|i| (i * 3) as u8).collect();
+        let prover = Prover::new(layout, &image).unwrap();
+        let (mut page_cache, mut multiproof) = prover.generate(&[0x40]).unwrap();
+        page_cache.get_mut(0x40).unwrap().data = vec![0xeeu8; 16];
+        multiproof
+            .insert(MultiproofEntry::new(
+                &layout,
+                layout.depth(),
+                0,
+                prover.root(),
+            ))
+            .unwrap();
+
+        // the root is calculated from the forged page rather than taken from the entry
+        let mut merkle_proof = MerkleProof::new(layout, &page_cache, &multiproof);
+        let forged_root = merkle_proof.calculate_root().unwrap();
+        assert_ne!(forged_root, prover.root());
+        multiproof.remove(0x0, 0x3ff);
+        let mut merkle_proof = MerkleProof::new(layout, &page_cache, &multiproof);
+        assert_eq!(merkle_proof.calculate_root(), Ok(forged_root));
+
+        multiproof
+            .insert(MultiproofEntry::new(
+                &layout,
+                layout.depth(),
+                0,
+                prover.root(),
+            ))
+            .unwrap();
+        let mut merkle_proof = MerkleProof::new(layout, &page_cache, &multiproof).strict(true);
+        assert_eq!(
+            merkle_proof.calculate_root(),
+            Err(ProofError::RedundantNode {
+                level: layout.depth() - 1,
+                address_low: 0x0,
+                address_high: 0x1ff,
+            })
+        );
+    }
+
+    #[test_log::test]
+    fn test_strict_unused_entries() {
+        let mut entries = test_entries();
+        entries.insert(
+            0,
+            // the page at 0x4 is known, so the entry for it is not used
+            MultiproofEntry {
+                address_low: 0x4,
+                address_high: 0x4,
+                hash: [0xeu8; HASH_SIZE],
+            },
+        );
+
+        let mut merkle_proof = MerkleProof::new(
+            MemoryLayout::default(),
+            PageCache::new(test_pages()),
+            Multiproof::try_new(entries).unwrap(),
+        )
+        .strict(true);
+        assert_eq!(
+            merkle_proof.calculate_root(),
+            Err(ProofError::UnusedMultiproofEntries {
+                count: 1,
+                address_low: 0x4,
+                address_high: 0x4,
+            })
+        );
+    }
+
+    #[test_log::test]
+    fn test_missing_node() {
+        let page_cache = PageCache::new(vec![Page {
+            data: vec![1u8; 1 << PAGE_LOG2_SIZE],
+            address: 0x4,
+        }]);
+        let multiproof = Multiproof::try_new([MultiproofEntry {
+            address_low: 0x10,
+            address_high: 0x1f,
+            hash: [0xdu8; HASH_SIZE],
+        }])
+        .unwrap();
+
+        let mut merkle_proof = MerkleProof::new(MemoryLayout::default(), page_cache, multiproof);
+        assert_eq!(
+            merkle_proof.calculate_root(),
+            Err(ProofError::MissingNode {
+                level: 0,
+                address_low: 0x0,
+                address_high: 0x3,
+            })
+        );
+    }
+
+    #[test_log::test]
+    fn test_overlapping_pages() {
+        let page_cache = PageCache::new(vec![
+            Page {
+                data: vec![1u8; 1 << PAGE_LOG2_SIZE],
+                address: 0x4,
+            },
+            Page {
+                data: vec![2u8; 1 << PAGE_LOG2_SIZE],
+                address: 0x4,
+            },
         ]);
 
-        let multiproof = Multiproof {
-            hashes: vec![
-                MultiproofEntry {
-                    address_low: 0x18,
-                    address_high: 0x1f,
-                    hash: [0xdu8; HASH_SIZE],
+        let mut merkle_proof =
+            MerkleProof::new(MemoryLayout::default(), page_cache, Multiproof::new());
+        assert_eq!(
+            merkle_proof.calculate_root(),
+            Err(ProofError::OverlappingPages { address: 0x4 })
+        );
+    }
+
+    #[test_log::test]
+    fn test_misaligned_page() {
+        let page_cache = PageCache::new(vec![Page {
+            data: vec![1u8; 1 << PAGE_LOG2_SIZE],
+            address: 0x5,
+        }]);
+
+        let mut merkle_proof =
+            MerkleProof::new(MemoryLayout::default(), page_cache, Multiproof::new());
+        assert_eq!(
+            merkle_proof.calculate_root(),
+            Err(ProofError::MisalignedPage { address: 0x5 })
+        );
+    }
+
+    #[test_log::test]
+    fn test_out_of_range() {
+        // the data beyond the memory chunk or off the tree is rejected in both modes
+        let page = |address| Page {
+            data: vec![1u8; 1 << PAGE_LOG2_SIZE],
+            address,
+        };
+        let entry = |address_low, address_high| MultiproofEntry {
+            address_low,
+            address_high,
+            hash: [0xeu8; HASH_SIZE],
+        };
+        let cases = [
+            (
+                vec![page(0x4), page(0x20)],
+                vec![],
+                ProofError::PageOutOfRange { address: 0x20 },
+            ),
+            (
+                test_pages(),
+                [test_entries(), vec![entry(0x20, 0x3f)]].concat(),
+                ProofError::EntryOutOfRange {
+                    address_low: 0x20,
+                    address_high: 0x3f,
                 },
-                MultiproofEntry {
-                    address_low: 0x10,
-                    address_high: 0x10,
-                    hash: [0xcu8; HASH_SIZE],
+            ),
+            (
+                test_pages(),
+                [test_entries(), vec![entry(0x2, 0x5)]].concat(),
+                ProofError::MisalignedEntry {
+                    address_low: 0x2,
+                    address_high: 0x5,
                 },
-                MultiproofEntry {
-                    address_low: 0x8,
-                    address_high: 0x8,
-                    hash: [0xbu8; HASH_SIZE],
+            ),
+        ];
+        for (pages, entries, error) in cases {
+            for strict in [false, true] {
+                let mut merkle_proof = MerkleProof::new(
+                    MemoryLayout::default(),
+                    PageCache::new(pages.clone()),
+                    Multiproof::try_new(entries.clone()).unwrap(),
+                )
+                .strict(strict);
+                assert_eq!(merkle_proof.calculate_root(), Err(error.clone()));
+            }
+        }
+    }
+
+    #[test_log::test]
+    fn test_custom_layout() {
+        let layout = MemoryLayout::new(4, 3);
+        let pages = || {
+            vec![
+                Page {
+                    data: vec![1u8; 8],
+                    address: 0x0,
+                },
+                Page {
+                    data: vec![2u8; 8],
+                    address: 0x8,
                 },
-                MultiproofEntry {
+            ]
+        };
+        let expected_root = {
+            let pages = pages();
+            Keccak256::hash_node(&pages[0].hash::<Keccak256>(), &pages[1].hash::<Keccak256>())
+        };
+
+        let mut merkle_proof = MerkleProof::new(layout, PageCache::new(pages()), Multiproof::new());
+        assert_eq!(merkle_proof.calculate_root(), Ok(expected_root));
+    }
+
+    #[test_log::test]
+    fn test_single_page() {
+        // the page is the root
+        let layout = MemoryLayout::new(4, 4);
+        let image = vec![7u8; 16];
+        let page = Page {
+            data: image.clone(),
+            address: 0x0,
+        };
+        let expected_root = page.hash::<Keccak256>();
+        let mut tree = MerkleTree::new(layout);
+        tree.update_page(0x0, image.clone()).unwrap();
+        assert_eq!(tree.root(), expected_root);
+        assert_eq!(Prover::new(layout, &image).unwrap().root(), expected_root);
+        assert_eq!(
+            root_from_reader::<Keccak256>(&image[..], &layout).unwrap(),
+            expected_root
+        );
+
+        let (page_cache, multiproof) = Prover::new(layout, &image)
+            .unwrap()
+            .generate(&[0x0])
+            .unwrap();
+        assert!(multiproof.is_empty());
+        let mut merkle_proof = MerkleProof::new(layout, page_cache, multiproof);
+        assert_eq!(merkle_proof.verify(&expected_root), Ok(()));
+
+        let multiproof =
+            Multiproof::try_new([MultiproofEntry::new(&layout, 0, 0, expected_root)]).unwrap();
+        let mut merkle_proof = MerkleProof::new(layout, PageCache::default(), multiproof);
+        assert_eq!(merkle_proof.verify(&expected_root), Ok(()));
+
+        let mut merkle_proof = MerkleProof::new(layout, PageCache::default(), Multiproof::new());
+        assert_eq!(
+            merkle_proof.calculate_root(),
+            Err(ProofError::MissingNode {
+                level: 0,
+                address_low: 0x0,
+                address_high: 0xf,
+            })
+        );
+        let mut merkle_proof =
+            MerkleProof::new(layout, PageCache::default(), Multiproof::new()).pristine(true);
+        assert_eq!(
+            merkle_proof.calculate_root(),
+            Ok(Page {
+                data: vec![0u8; 16],
+                address: 0x0,
+            }
+            .hash::<Keccak256>())
+        );
+    }
+
+    #[test_log::test]
+    fn test_invalid_page_size() {
+        let page_cache = PageCache::new(vec![Page {
+            data: vec![1u8; 8],
+            address: 0x4,
+        }]);
+
+        let mut merkle_proof =
+            MerkleProof::new(MemoryLayout::default(), page_cache, Multiproof::new());
+        assert_eq!(
+            merkle_proof.calculate_root(),
+            Err(ProofError::InvalidPageSize {
+                address: 0x4,
+                size: 8
+            })
+        );
+    }
+
+    #[test_log::test]
+    fn test_sparse_64bit_layout() {
+        let layout = MemoryLayout::new(64, 12);
+        let page = Page {
+            data: vec![1u8; 1 << 12],
+            address: 0x1000,
+        };
+        let mut expected_root = page.hash::<Keccak256>();
+        let mut entries = Vec::new();
+        for level in 0..layout.depth() {
+            let sibling = [level as u8; HASH_SIZE];
+            if level == 0 {
+                // the page is the right child
+                entries.push(MultiproofEntry {
                     address_low: 0x0,
                     address_high: 0x0,
-                    hash: [0xau8; HASH_SIZE],
-                },
-            ],
+                    hash: sibling,
+                });
+                expected_root = Keccak256::hash_node(&sibling, &expected_root);
+            } else {
+                let (address_low, address_high) = layout.node_range(level, 1);
+                entries.push(MultiproofEntry {
+                    address_low,
+                    address_high,
+                    hash: sibling,
+                });
+                expected_root = Keccak256::hash_node(&expected_root, &sibling);
+            }
+        }
+
+        let mut merkle_proof = MerkleProof::new(
+            layout,
+            PageCache::new(vec![page]),
+            Multiproof::try_new(entries).unwrap(),
+        )
+        .strict(true);
+        assert_eq!(merkle_proof.calculate_root(), Ok(expected_root));
+    }
+
+    #[test_log::test]
+    fn test_hashers() {
+        fn expected_root<H: MerkleHasher>() -> ProofHash {
+            let pages = test_pages();
+            let entry = |value: u8| [value; HASH_SIZE];
+            let left = H::hash_node(
+                &H::hash_node(&entry(0xa), &pages[0].hash::<H>()),
+                &H::hash_node(&entry(0xb), &pages[1].hash::<H>()),
+            );
+            let right = H::hash_node(
+                &H::hash_node(&entry(0xc), &pages[2].hash::<H>()),
+                &entry(0xd),
+            );
+            H::hash_node(&left, &right)
+        }
+        fn calculate_root<H: MerkleHasher>() -> Result<ProofHash, ProofError> {
+            MerkleProof::<H>::with_hasher(
+                MemoryLayout::default(),
+                PageCache::new(test_pages()),
+                Multiproof::try_new(test_entries()).unwrap(),
+            )
+            .calculate_root()
+        }
+
+        assert_eq!(expected_root::<Keccak256>(), EXPECTED_ROOT_HASH);
+        assert_eq!(calculate_root::<Keccak256>(), Ok(EXPECTED_ROOT_HASH));
+        assert_eq!(calculate_root::<Cartesi>(), Ok(expected_root::<Cartesi>()));
+        #[cfg(feature = "sha2")]
+        assert_eq!(
+            calculate_root::<crate::proof::hasher::Sha256>(),
+            Ok(expected_root::<crate::proof::hasher::Sha256>())
+        );
+        #[cfg(feature = "blake3")]
+        assert_eq!(
+            calculate_root::<crate::proof::hasher::Blake3>(),
+            Ok(expected_root::<crate::proof::hasher::Blake3>())
+        );
+    }
+
+    #[test_log::test]
+    fn test_verify() {
+        let merkle_proof = || {
+            MerkleProof::new(
+                MemoryLayout::default(),
+                PageCache::new(test_pages()),
+                Multiproof::try_new(test_entries()).unwrap(),
+            )
         };
+        assert_eq!(merkle_proof().verify(&EXPECTED_ROOT_HASH), Ok(()));
+        assert_eq!(
+            merkle_proof().verify(&[0u8; HASH_SIZE]),
+            Err(ProofError::RootMismatch {
+                expected: [0u8; HASH_SIZE],
+                computed: EXPECTED_ROOT_HASH,
+            })
+        );
 
-        let mut merkle_proof = MerkleProof::new(page_cache, multiproof);
-        let calculated_root = merkle_proof.calculate_root().expect("Invalid input data");
-        assert_eq!(calculated_root, EXPECTED_ROOT_HASH);
+        // the verification is always strict
+        let mut entries = test_entries();
+        entries.insert(
+            0,
+            // the page at 0x4 is known, so the entry for it is not used
+            MultiproofEntry {
+                address_low: 0x4,
+                address_high: 0x4,
+                hash: [0xeu8; HASH_SIZE],
+            },
+        );
+        let mut merkle_proof = MerkleProof::new(
+            MemoryLayout::default(),
+            PageCache::new(test_pages()),
+            Multiproof::try_new(entries).unwrap(),
+        );
+        assert!(matches!(
+            merkle_proof.verify(&EXPECTED_ROOT_HASH),
+            Err(ProofError::UnusedMultiproofEntries { .. })
+        ));
+        // but it leaves the mode of the proof as it was
+        assert_eq!(merkle_proof.calculate_root(), Ok(EXPECTED_ROOT_HASH));
+    }
+
+    #[test_log::test]
+    fn test_apply_writes() {
+        let layout = MemoryLayout::new(10, 4);
+        let mut image: Vec<u8> = (0..1u32 << 10).map(|i| (i * 7) as u8).collect();
+        let old_root = Prover::<Keccak256>::with_hasher(layout, &image)
+            .unwrap()
+            .root();
+        let (page_cache, multiproof) = Prover::<Keccak256>::with_hasher(layout, &image)
+            .unwrap()
+            .generate(&[0x40, 0x50, 0x3f0])
+            .unwrap();
+        let mut merkle_proof =
+            MerkleProof::<Keccak256>::with_hasher(layout, &page_cache, &multiproof).strict(true);
+
+        let writes = [(0x50, vec![1u8; 16]), (0x3f0, vec![0u8; 16])];
+        for (address, data) in &writes {
+            image[*address..*address + 16].copy_from_slice(data);
+        }
+        let new_root = Prover::<Keccak256>::with_hasher(layout, &image)
+            .unwrap()
+            .root();
+        assert_eq!(
+            merkle_proof.apply_writes(
+                writes
+                    .iter()
+                    .map(|(address, data)| (*address as PageAddress, data.clone()))
+            ),
+            Ok((old_root, new_root))
+        );
+        // the proof now describes the new state, the borrowed pages are left untouched
+        assert_eq!(merkle_proof.calculate_root(), Ok(new_root));
+        assert_eq!(page_cache.get(0x50).unwrap().data[0], 0x30);
+
+        // a page that is not in the proof can't be written
+        assert_eq!(
+            merkle_proof.apply_writes([(0x60, vec![1u8; 16])]),
+            Err(ProofError::MissingPage { address: 0x60 })
+        );
+        assert_eq!(
+            merkle_proof.apply_writes([(0x40, vec![1u8; 16]), (0x48, vec![1u8; 16])]),
+            Err(ProofError::MisalignedPage { address: 0x48 })
+        );
+        assert_eq!(
+            merkle_proof.apply_writes([(0x40, vec![1u8; 8])]),
+            Err(ProofError::InvalidPageSize {
+                address: 0x40,
+                size: 8
+            })
+        );
+        assert_eq!(merkle_proof.calculate_root(), Ok(new_root));
+
+        // an entry above a page would hide the write, even in the non-strict mode
+        let prover = Prover::<Keccak256>::with_hasher(layout, &image).unwrap();
+        let (page_cache, mut multiproof) = prover.generate(&[0x40, 0x3f0]).unwrap();
+        let sibling = multiproof.remove(0x50, 0x50).unwrap();
+        let parent = Keccak256::hash_node(&Keccak256::hash_leaf(&image[0x40..0x50]), &sibling);
+        multiproof
+            .insert(MultiproofEntry::new(&layout, 1, 2, parent))
+            .unwrap();
+        let mut merkle_proof =
+            MerkleProof::<Keccak256>::with_hasher(layout, &page_cache, &multiproof);
+        assert_eq!(merkle_proof.calculate_root(), Ok(new_root));
+        assert_eq!(
+            merkle_proof.apply_writes([(0x40, vec![2u8; 16])]),
+            Err(ProofError::RedundantNode {
+                level: 0,
+                address_low: 0x40,
+                address_high: 0x4f,
+            })
+        );
+        assert_eq!(merkle_proof.calculate_root(), Ok(new_root));
+    }
+
+    #[test_log::test]
+    fn test_pristine() {
+        let layout = MemoryLayout::new(12, 4);
+        let mut image = vec![0u8; 1 << 12];
+        image[0x120..0x130].fill(1);
+        image[0xf00..0xf10].fill(2);
+        let prover = Prover::new(layout, &image).unwrap();
+        let root = prover.root();
+
+        // a complete proof is accepted as well
+        let (page_cache, multiproof) = prover.generate(&[0x120]).unwrap();
+        let mut merkle_proof = MerkleProof::new(layout, page_cache, multiproof)
+            .strict(true)
+            .pristine(true);
+        assert_eq!(merkle_proof.calculate_root(), Ok(root));
+
+        let prover = prover.pristine(true);
+        let (page_cache, multiproof) = prover.generate(&[0x120]).unwrap();
+        // only the sibling subtree holding the other non-zero page is left
+        assert_eq!(multiproof.len(), 1);
+        let mut merkle_proof = MerkleProof::new(layout, page_cache, multiproof).strict(true);
+        assert!(merkle_proof.calculate_root().is_err());
+
+        let (page_cache, multiproof) = prover.generate(&[0x120]).unwrap();
+        let mut merkle_proof = MerkleProof::new(layout, page_cache, multiproof)
+            .strict(true)
+            .pristine(true);
+        assert_eq!(merkle_proof.calculate_root(), Ok(root));
+
+        // no data at all stands for the pristine memory
+        let image = vec![0u8; 1 << 12];
+        let (page_cache, multiproof) = Prover::new(layout, &image)
+            .unwrap()
+            .pristine(true)
+            .generate(&[])
+            .unwrap();
+        assert_eq!(multiproof.len(), 0);
+        let mut merkle_proof = MerkleProof::new(layout, page_cache, multiproof).pristine(true);
+        assert_eq!(
+            merkle_proof.calculate_root(),
+            Ok(PristineHashes::<Keccak256>::new(layout).get(layout.depth()))
+        );
     }
 }
@@ -0,0 +1,268 @@
+use crate::proof::{
+    access_log::Access,
+    error::ProofError,
+    hasher::{Keccak256, MerkleHasher},
+    leaf_proof::LeafProof,
+    page_cache::Page,
+    parallel,
+    pristine::PristineHashes,
+    types::{MemoryLayout, PageAddress, PageData, ProofHash},
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A persistent Merkle tree of the memory chunk described by `layout`. Unlike `MerkleProof`, it
+/// keeps the hashes of every level, so the root can be updated after page writes by rehashing
+/// only the paths from the written pages to the root.
+/// Only the nodes that differ from the pristine (zero-filled) subtrees are stored, which keeps
+/// the tree small for sparse memories.
+pub struct MerkleTree<H: MerkleHasher = Keccak256> {
+    layout: MemoryLayout,
+    /// Non-pristine nodes of every level by index, 0 being the level of the pages.
+    levels: Vec<BTreeMap<u64, ProofHash>>,
+    /// Non-pristine pages by address.
+    pages: BTreeMap<PageAddress, PageData>,
+    pristine: PristineHashes<H>,
+}
+
+impl MerkleTree {
+    /// Creates a tree of the pristine memory using Keccak-256.
+    pub fn new(layout: MemoryLayout) -> Self {
+        Self::with_hasher(layout)
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Creates a tree of the pristine memory using the hash function `H`.
+    pub fn with_hasher(layout: MemoryLayout) -> Self {
+        Self {
+            layout,
+            levels: vec![BTreeMap::new(); layout.depth() + 1],
+            pages: BTreeMap::new(),
+            pristine: PristineHashes::new(layout),
+        }
+    }
+
+    pub fn layout(&self) -> &MemoryLayout {
+        &self.layout
+    }
+
+    /// Returns the hash of the node at `index` at `level`, 0 being the level of the pages, or
+    /// `None` if there is no such node.
+    pub fn node(&self, level: usize, index: u64) -> Option<ProofHash> {
+        if level > self.layout.depth() || index > self.layout.last_index(level) {
+            return None;
+        }
+        Some(
+            self.levels[level]
+                .get(&index)
+                .copied()
+                .unwrap_or_else(|| self.pristine.get(level)),
+        )
+    }
+
+    /// Returns the Merkle tree root.
+    pub fn root(&self) -> ProofHash {
+        self.node(self.layout.depth(), 0).unwrap()
+    }
+
+    /// Returns the data of the page at `address`, or `None` if the page is pristine.
+    pub fn page(&self, address: PageAddress) -> Option<&[u8]> {
+        self.pages.get(&address).map(Vec::as_slice)
+    }
+
+    /// Generates the leaf proof for the page at `address`.
+    pub fn leaf_proof(&self, address: PageAddress) -> Result<LeafProof, ProofError> {
+        self.check_address(address)?;
+        let index = address >> self.layout.page_log2_size();
+        let data = match self.page(address) {
+            Some(data) => data.to_vec(),
+            None => vec![0u8; self.layout.page_size() as usize],
+        };
+        Ok(LeafProof {
+            page: Page { data, address },
+            siblings: (0..self.layout.depth())
+                .map(|level| self.node(level, (index >> level) ^ 1).unwrap())
+                .collect(),
+        })
+    }
+
+    /// Records a read of the page at `address` for an access log.
+    pub fn log_read(&self, address: PageAddress) -> Result<Access, ProofError> {
+        let leaf_proofs = [self.leaf_proof(address)?];
+        let (_, multiproof) = LeafProof::to_multiproof::<H>(&self.layout, &leaf_proofs)?;
+        let [leaf_proof] = leaf_proofs;
+        Ok(Access::read(address, leaf_proof.page.data, multiproof))
+    }
+
+    /// Writes the page at `address` and records the write for an access log.
+    pub fn log_write(
+        &mut self,
+        address: PageAddress,
+        data: PageData,
+    ) -> Result<Access, ProofError> {
+        self.check_page(address, &data)?;
+        let access = self.log_read(address)?;
+        self.update_page(address, data.clone())?;
+        Ok(Access::write(
+            address,
+            access.old_value,
+            data,
+            access.multiproof,
+        ))
+    }
+
+    fn set_node(&mut self, level: usize, index: u64, hash: ProofHash) {
+        if hash == self.pristine.get(level) {
+            self.levels[level].remove(&index);
+        } else {
+            self.levels[level].insert(index, hash);
+        }
+    }
+
+    fn check_address(&self, address: PageAddress) -> Result<(), ProofError> {
+        if !self.layout.is_page_aligned(address) {
+            return Err(ProofError::MisalignedPage { address });
+        }
+        if address > self.layout.last_address() {
+            return Err(ProofError::PageOutOfRange { address });
+        }
+        Ok(())
+    }
+
+    fn check_page(&self, address: PageAddress, data: &[u8]) -> Result<(), ProofError> {
+        self.check_address(address)?;
+        if data.len() as u64 != self.layout.page_size() {
+            return Err(ProofError::InvalidPageSize {
+                address,
+                size: data.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Writes the page at `address` and updates the hashes on the path to the root.
+    pub fn update_page(&mut self, address: PageAddress, data: PageData) -> Result<(), ProofError> {
+        self.update_pages([(address, data)])
+    }
+
+    /// Writes the given pages and updates the hashes on their paths to the root. The common
+    /// ancestors of the pages are hashed once. The tree is left untouched if any of the pages
+    /// is invalid.
+    pub fn update_pages<I>(&mut self, pages: I) -> Result<(), ProofError>
+    where
+        I: IntoIterator<Item = (PageAddress, PageData)>,
+    {
+        let pages: Vec<(PageAddress, PageData)> = pages.into_iter().collect();
+        for (address, data) in &pages {
+            self.check_page(*address, data)?;
+        }
+
+        let hashes = parallel::map(&pages, |(_, data)| H::hash_leaf(data));
+        let mut dirty = BTreeSet::new();
+        for ((address, data), hash) in pages.into_iter().zip(hashes) {
+            let index = address >> self.layout.page_log2_size();
+            self.set_node(0, index, hash);
+            if data.iter().all(|byte| *byte == 0) {
+                self.pages.remove(&address);
+            } else {
+                self.pages.insert(address, data);
+            }
+            dirty.insert(index >> 1);
+        }
+        for level in 1..=self.layout.depth() {
+            log::debug!(">>> Rehashing {} nodes at level {}", dirty.len(), level);
+            let children: Vec<(u64, ProofHash, ProofHash)> = dirty
+                .iter()
+                .map(|index| {
+                    (
+                        *index,
+                        self.node(level - 1, index << 1).unwrap(),
+                        self.node(level - 1, (index << 1) | 1).unwrap(),
+                    )
+                })
+                .collect();
+            let hashes = parallel::map(&children, |(_, left, right)| H::hash_node(left, right));
+            for ((index, _, _), hash) in children.into_iter().zip(hashes) {
+                self.set_node(level, index, hash);
+            }
+            dirty = dirty.into_iter().map(|index| index >> 1).collect();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::{hasher::Keccak256, prover::Prover};
+
+    #[test_log::test]
+    fn test_update_pages() {
+        let layout = MemoryLayout::new(10, 4);
+        let mut image = vec![0u8; 1 << 10];
+        let mut tree = MerkleTree::<Keccak256>::with_hasher(layout);
+        assert_eq!(
+            tree.root(),
+            Prover::<Keccak256>::with_hasher(layout, &image)
+                .unwrap()
+                .root()
+        );
+
+        let writes = [(0x0, 1u8), (0x10, 2), (0x1f0, 3), (0x3f0, 4)];
+        for (address, value) in writes {
+            image[address as usize..address as usize + 16].fill(value);
+            tree.update_page(address, vec![value; 16]).unwrap();
+            assert_eq!(
+                tree.root(),
+                Prover::<Keccak256>::with_hasher(layout, &image)
+                    .unwrap()
+                    .root()
+            );
+        }
+        assert_eq!(tree.page(0x10), Some(&[2u8; 16][..]));
+        assert_eq!(tree.page(0x20), None);
+
+        // a batch update gives the same result
+        let mut batch = MerkleTree::<Keccak256>::with_hasher(layout);
+        batch
+            .update_pages(
+                writes
+                    .iter()
+                    .map(|(address, value)| (*address, vec![*value; 16])),
+            )
+            .unwrap();
+        assert_eq!(batch.root(), tree.root());
+
+        // zeroing the pages brings the tree back to the pristine state
+        batch
+            .update_pages(writes.iter().map(|(address, _)| (*address, vec![0u8; 16])))
+            .unwrap();
+        assert_eq!(
+            batch.root(),
+            MerkleTree::<Keccak256>::with_hasher(layout).root()
+        );
+        assert!(batch.levels.iter().all(BTreeMap::is_empty));
+    }
+
+    #[test_log::test]
+    fn test_update_invalid_page() {
+        let mut tree = MerkleTree::new(MemoryLayout::default());
+        let root = tree.root();
+        assert_eq!(
+            tree.update_pages([(0x4, vec![1u8; 4]), (0x6, vec![1u8; 4])]),
+            Err(ProofError::MisalignedPage { address: 0x6 })
+        );
+        assert_eq!(tree.root(), root);
+        assert_eq!(
+            tree.update_page(0x20, vec![1u8; 4]),
+            Err(ProofError::PageOutOfRange { address: 0x20 })
+        );
+        assert_eq!(
+            tree.update_page(0x8, vec![1u8; 8]),
+            Err(ProofError::InvalidPageSize {
+                address: 0x8,
+                size: 8
+            })
+        );
+    }
+}
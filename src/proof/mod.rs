@@ -1,5 +1,17 @@
+pub mod access_log;
+pub mod codec;
+pub mod error;
+pub mod hasher;
+#[cfg(feature = "serde")]
+pub mod hex;
+pub mod leaf_proof;
 pub mod merkle_proof;
-
-mod multiproof;
-mod page_cache;
-mod types;
+pub mod merkle_tree;
+pub mod multiproof;
+pub mod page_cache;
+mod parallel;
+pub mod pristine;
+pub mod prover;
+pub mod stream;
+pub mod types;
+pub mod word_proof;
@@ -1,30 +1,613 @@
-use crate::proof::types::{PageAddress, ProofHash};
+use crate::proof::{
+    error::ProofError,
+    hasher::MerkleHasher,
+    merkle_proof::MerkleProof,
+    page_cache::{Page, PageCache},
+    types::{MemoryLayout, PageAddress, ProofHash},
+};
+use std::{borrow::Cow, collections::BTreeMap, marker::PhantomData};
 
 /// Multiproof entry is a hash that is used to complement the missing pages in the page cache.
 /// `address_low` and `address_high` define the memory range that the `hash` is calculated for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MultiproofEntry {
+    #[cfg_attr(feature = "serde", serde(with = "crate::proof::hex::address"))]
     pub address_low: PageAddress,
+    #[cfg_attr(feature = "serde", serde(with = "crate::proof::hex::address"))]
     pub address_high: PageAddress,
+    #[cfg_attr(feature = "serde", serde(with = "crate::proof::hex::array"))]
     pub hash: ProofHash,
 }
 
+impl MultiproofEntry {
+    /// Creates an entry for the node at `index` at `level`, 0 being the level of the pages.
+    pub fn new(layout: &MemoryLayout, level: usize, index: u64, hash: ProofHash) -> Self {
+        let (address_low, address_high) = Self::range(layout, level, index);
+        Self {
+            address_low,
+            address_high,
+            hash,
+        }
+    }
+
+    /// Returns the memory range of the node at `index` at `level`, as it is encoded in the
+    /// entries. The entries for the pages use the page address as both bounds.
+    pub fn range(layout: &MemoryLayout, level: usize, index: u64) -> (PageAddress, PageAddress) {
+        let (address_low, address_high) = layout.node_range(level, index);
+        if level == 0 {
+            (address_low, address_low)
+        } else {
+            (address_low, address_high)
+        }
+    }
+
+    /// Returns the `(level, index)` of the node that the entry complements, or `None` if the
+    /// range doesn't describe a node of the tree.
+    pub fn node(&self, layout: &MemoryLayout) -> Option<(usize, u64)> {
+        node(layout, self.address_low, self.address_high)
+    }
+
+    /// Checks that the entry range is the range of a node of the tree described by `layout`.
+    pub fn validate(&self, layout: &MemoryLayout) -> Result<(), ProofError> {
+        let (address_low, address_high) = (self.address_low, self.address_high);
+        if address_high < address_low {
+            return Err(ProofError::InvalidEntryRange {
+                address_low,
+                address_high,
+            });
+        }
+        if address_high > layout.last_address() {
+            return Err(ProofError::EntryOutOfRange {
+                address_low,
+                address_high,
+            });
+        }
+        match self.node(layout) {
+            Some(_) => Ok(()),
+            None => Err(ProofError::MisalignedEntry {
+                address_low,
+                address_high,
+            }),
+        }
+    }
+}
+
+/// Inverse of `MultiproofEntry::range`.
+fn node(
+    layout: &MemoryLayout,
+    address_low: PageAddress,
+    address_high: PageAddress,
+) -> Option<(usize, u64)> {
+    let mask = address_high.checked_sub(address_low)?;
+    let level = if mask == 0 {
+        0
+    } else if mask.checked_add(1).is_none_or(u64::is_power_of_two) {
+        (mask.count_ones() as usize).checked_sub(layout.page_log2_size())?
+    } else {
+        return None;
+    };
+    let index = address_low
+        .checked_shr((layout.page_log2_size() + level) as u32)
+        .unwrap_or(0);
+    (level <= layout.depth()
+        && index <= layout.last_index(level)
+        && MultiproofEntry::range(layout, level, index) == (address_low, address_high))
+        .then_some((level, index))
+}
+
 /// Multiproof is a collection of hashes that are used to complement the missing pages in the page
 /// cache. Multiproof is used to calculate the Merkle tree root hash.
+/// The entries are indexed by their `(address_low, address_high)` range, so they can be supplied
+/// in any order. With the `serde` feature it is serialized as the list of its entries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(into = "Vec<MultiproofEntry>", try_from = "Vec<MultiproofEntry>")
+)]
 pub struct Multiproof {
-    pub hashes: Vec<MultiproofEntry>,
+    entries: BTreeMap<(PageAddress, PageAddress), ProofHash>,
 }
 
 impl Multiproof {
-    /// Get next available entry from the multiproof.
-    pub fn get_next(&mut self) -> Option<MultiproofEntry> {
-        self.hashes.pop()
+    /// Creates an empty multiproof.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a multiproof from the given entries. Fails if more than one entry is supplied for
+    /// the same range, or if a range ends before it starts.
+    pub fn try_new(entries: impl IntoIterator<Item = MultiproofEntry>) -> Result<Self, ProofError> {
+        let mut multiproof = Self::new();
+        for entry in entries {
+            multiproof.insert(entry)?;
+        }
+        Ok(multiproof)
+    }
+
+    /// Adds the entry to the multiproof. Fails if there already is an entry for the same range.
+    pub fn insert(&mut self, entry: MultiproofEntry) -> Result<(), ProofError> {
+        let range = (entry.address_low, entry.address_high);
+        if entry.address_high < entry.address_low {
+            return Err(ProofError::InvalidEntryRange {
+                address_low: entry.address_low,
+                address_high: entry.address_high,
+            });
+        }
+        match self.entries.get(&range) {
+            Some(hash) if *hash == entry.hash => Err(ProofError::DuplicateMultiproofEntry {
+                address_low: entry.address_low,
+                address_high: entry.address_high,
+            }),
+            Some(_) => Err(ProofError::ConflictingMultiproofEntries {
+                address_low: entry.address_low,
+                address_high: entry.address_high,
+            }),
+            None => {
+                self.entries.insert(range, entry.hash);
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks that every entry range is the range of a node of the tree described by `layout`.
+    pub fn validate(&self, layout: &MemoryLayout) -> Result<(), ProofError> {
+        self.iter().try_for_each(|entry| entry.validate(layout))
+    }
+
+    /// Checks if there are any entries starting within `address_low..=address_high`.
+    pub fn has_entries_within(&self, address_low: PageAddress, address_high: PageAddress) -> bool {
+        self.entries
+            .range((address_low, 0)..=(address_high, PageAddress::MAX))
+            .next()
+            .is_some()
+    }
+
+    /// Returns the hash of the entry for `address_low..=address_high`.
+    pub fn get(&self, address_low: PageAddress, address_high: PageAddress) -> Option<&ProofHash> {
+        self.entries.get(&(address_low, address_high))
+    }
+
+    /// Removes the entry for `address_low..=address_high` and returns its hash.
+    pub fn remove(
+        &mut self,
+        address_low: PageAddress,
+        address_high: PageAddress,
+    ) -> Option<ProofHash> {
+        self.entries.remove(&(address_low, address_high))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterates over the entries, sorted by their ranges.
+    pub fn iter(&self) -> impl Iterator<Item = MultiproofEntry> + '_ {
+        self.entries
+            .iter()
+            .map(|(&(address_low, address_high), hash)| MultiproofEntry {
+                address_low,
+                address_high,
+                hash: *hash,
+            })
+    }
+
+    /// Returns the `(index, hash)` pairs of the entries for the nodes at `level`, sorted by
+    /// index. The entries are expected to be checked with `validate`, the ones that don't
+    /// describe a node of the tree are skipped.
+    pub(crate) fn level_entries(
+        &self,
+        layout: &MemoryLayout,
+        level: usize,
+    ) -> Vec<(u64, ProofHash)> {
+        self.entries
+            .iter()
+            .filter_map(|(&(address_low, address_high), hash)| {
+                match node(layout, address_low, address_high) {
+                    Some((entry_level, index)) if entry_level == level => Some((index, *hash)),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+impl From<Multiproof> for Cow<'_, Multiproof> {
+    fn from(multiproof: Multiproof) -> Self {
+        Cow::Owned(multiproof)
+    }
+}
+
+impl<'a> From<&'a Multiproof> for Cow<'a, Multiproof> {
+    fn from(multiproof: &'a Multiproof) -> Self {
+        Cow::Borrowed(multiproof)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Multiproof> for Vec<MultiproofEntry> {
+    fn from(multiproof: Multiproof) -> Self {
+        multiproof.iter().collect()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<Vec<MultiproofEntry>> for Multiproof {
+    type Error = ProofError;
+
+    fn try_from(entries: Vec<MultiproofEntry>) -> Result<Self, ProofError> {
+        Multiproof::try_new(entries)
+    }
+}
+
+/// Calculates the root of the proof made of `page_cache` and `multiproof` in the strict mode, so
+/// that the proof holds no entry shadowing any of its pages.
+pub(crate) fn strict_root<H: MerkleHasher>(
+    layout: &MemoryLayout,
+    page_cache: &PageCache,
+    multiproof: &Multiproof,
+) -> Result<ProofHash, ProofError> {
+    MerkleProof::<H>::with_hasher(*layout, page_cache, multiproof)
+        .strict(true)
+        .calculate_root()
+}
+
+/// Derives the hashes of the nodes from the pages and the multiproof entries below them, without
+/// descending into the subtrees that hold neither. The entry of a node is taken over the pages
+/// below it, so the proofs must be checked with `strict_root` first.
+pub(crate) struct KnownNodes<'a, H: MerkleHasher> {
+    layout: MemoryLayout,
+    page_cache: &'a PageCache,
+    multiproof: &'a Multiproof,
+    hasher: PhantomData<H>,
+}
+
+impl<'a, H: MerkleHasher> KnownNodes<'a, H> {
+    pub fn new(
+        layout: MemoryLayout,
+        page_cache: &'a PageCache,
+        multiproof: &'a Multiproof,
+    ) -> Self {
+        Self {
+            layout,
+            page_cache,
+            multiproof,
+            hasher: PhantomData,
+        }
+    }
+
+    /// Returns the hash of the node at `index` at `level`, or `None` if it can't be derived.
+    pub fn hash(&self, level: usize, index: u64) -> Option<ProofHash> {
+        let (address_low, address_high) = MultiproofEntry::range(&self.layout, level, index);
+        if let Some(hash) = self.multiproof.get(address_low, address_high) {
+            return Some(*hash);
+        }
+        let (address_low, address_high) = self.layout.node_range(level, index);
+        if level == 0 {
+            return self.page_cache.get(address_low).map(Page::hash::<H>);
+        }
+        if !self.page_cache.has_pages_within(address_low, address_high)
+            && !self
+                .multiproof
+                .has_entries_within(address_low, address_high)
+        {
+            return None;
+        }
+        Some(H::hash_node(
+            &self.hash(level - 1, index << 1)?,
+            &self.hash(level - 1, (index << 1) | 1)?,
+        ))
     }
 
-    /// Check if the multiproof has the next entry necessary for calculating the hash.
-    /// Multiproof should complement the missing pages.
-    pub fn has_next(&self, address_low: PageAddress, address_high: PageAddress) -> bool {
-        self.hashes.last().map_or(false, |entry| {
-            entry.address_low == address_low && entry.address_high == address_high
+    /// Returns the hash of the node at `index` at `level`, failing if it can't be derived.
+    pub fn require(&self, level: usize, index: u64) -> Result<ProofHash, ProofError> {
+        self.hash(level, index).ok_or_else(|| {
+            let (address_low, address_high) = self.layout.node_range(level, index);
+            ProofError::MissingNode {
+                level,
+                address_low,
+                address_high,
+            }
         })
     }
+
+    /// Returns the minimal multiproof for the pages with the given sorted indices.
+    pub fn multiproof(&self, pages: &[u64]) -> Result<Multiproof, ProofError> {
+        let mut multiproof = Multiproof::new();
+        self.collect_entries(self.layout.depth(), 0, pages, &mut multiproof)?;
+        Ok(multiproof)
+    }
+
+    /// Collects the multiproof entries for the subtree at `index` at `level`. `pages` are the
+    /// sorted indices of the pages within the subtree.
+    fn collect_entries(
+        &self,
+        level: usize,
+        index: u64,
+        pages: &[u64],
+        multiproof: &mut Multiproof,
+    ) -> Result<(), ProofError> {
+        if pages.is_empty() {
+            let hash = self.require(level, index)?;
+            return multiproof.insert(MultiproofEntry::new(&self.layout, level, index, hash));
+        }
+        if level == 0 {
+            return Ok(());
+        }
+        let right_index = (index << 1) | 1;
+        let split = pages.partition_point(|page| page >> (level - 1) < right_index);
+        self.collect_entries(level - 1, index << 1, &pages[..split], multiproof)?;
+        self.collect_entries(level - 1, right_index, &pages[split..], multiproof)
+    }
+}
+
+impl Multiproof {
+    /// Merges the proofs of different pages against the same root into one. The entries that
+    /// can be calculated from the pages of the other proofs are dropped, so the merged
+    /// multiproof is minimal. Every proof is checked in the strict mode.
+    pub fn merge<H: MerkleHasher>(
+        layout: &MemoryLayout,
+        proofs: &[(PageCache, Multiproof)],
+    ) -> Result<(PageCache, Multiproof), ProofError> {
+        let mut root = None;
+        let mut pages: BTreeMap<PageAddress, Page> = BTreeMap::new();
+        let mut entries = Multiproof::new();
+        for (page_cache, multiproof) in proofs {
+            let computed = strict_root::<H>(layout, page_cache, multiproof)?;
+            match root {
+                Some(expected) if expected != computed => {
+                    return Err(ProofError::RootMismatch { expected, computed })
+                }
+                _ => root = Some(computed),
+            }
+            // the proofs lead to the same root, so the pages at the same address hold the same
+            // data
+            for page in page_cache.iter() {
+                pages.entry(page.address).or_insert_with(|| page.clone());
+            }
+            for entry in multiproof.iter() {
+                match entries.insert(entry) {
+                    Err(ProofError::DuplicateMultiproofEntry { .. }) => {}
+                    result => result?,
+                }
+            }
+        }
+
+        let page_cache = PageCache::try_new(layout, pages.into_values().collect())?;
+        let indices: Vec<u64> = page_cache
+            .iter()
+            .map(|page| page.address >> layout.page_log2_size())
+            .collect();
+        let multiproof =
+            KnownNodes::<H>::new(*layout, &page_cache, &entries).multiproof(&indices)?;
+        Ok((page_cache, multiproof))
+    }
+
+    /// Extracts the proof of the given `pages` from the proof of a larger set of pages. The
+    /// hashes of the subtrees holding only the pages that are left out are calculated from
+    /// them. The larger proof is checked in the strict mode.
+    pub fn split<H: MerkleHasher>(
+        layout: &MemoryLayout,
+        page_cache: &PageCache,
+        multiproof: &Multiproof,
+        pages: &[PageAddress],
+    ) -> Result<(PageCache, Multiproof), ProofError> {
+        strict_root::<H>(layout, page_cache, multiproof)?;
+        let mut addresses = pages.to_vec();
+        addresses.sort_unstable();
+        addresses.dedup();
+        let pages = addresses
+            .iter()
+            .map(|address| {
+                page_cache
+                    .get(*address)
+                    .cloned()
+                    .ok_or(ProofError::MissingPage { address: *address })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let indices: Vec<u64> = addresses
+            .iter()
+            .map(|address| address >> layout.page_log2_size())
+            .collect();
+        let multiproof =
+            KnownNodes::<H>::new(*layout, page_cache, multiproof).multiproof(&indices)?;
+        Ok((PageCache::try_new(layout, pages)?, multiproof))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::{hasher::Keccak256, prover::Prover, types::HASH_SIZE};
+
+    #[test_log::test]
+    fn test_node() {
+        let layout = MemoryLayout::default();
+        for level in 0..=layout.depth() {
+            for index in 0..=layout.last_index(level) {
+                let entry = MultiproofEntry::new(&layout, level, index, [0u8; HASH_SIZE]);
+                assert_eq!(entry.node(&layout), Some((level, index)));
+            }
+        }
+        let node = |address_low, address_high| {
+            MultiproofEntry {
+                address_low,
+                address_high,
+                hash: [0u8; HASH_SIZE],
+            }
+            .node(&layout)
+        };
+        // a page range that doesn't follow the page entry convention
+        assert_eq!(node(0x4, 0x7), None);
+        assert_eq!(node(0x5, 0x5), None);
+        assert_eq!(node(0x4, 0xb), None);
+        assert_eq!(node(0x8, 0x17), None);
+        assert_eq!(node(0x20, 0x3f), None);
+        assert_eq!(node(0x20, 0x20), None);
+
+        let layout = MemoryLayout::new(64, 12);
+        let root = MultiproofEntry::new(&layout, layout.depth(), 0, [0u8; HASH_SIZE]);
+        assert_eq!((root.address_low, root.address_high), (0, u64::MAX));
+        assert_eq!(root.node(&layout), Some((layout.depth(), 0)));
+    }
+
+    #[test_log::test]
+    fn test_validate() {
+        let layout = MemoryLayout::default();
+        let entry = |address_low, address_high| MultiproofEntry {
+            address_low,
+            address_high,
+            hash: [0u8; HASH_SIZE],
+        };
+        assert_eq!(entry(0x4, 0x4).validate(&layout), Ok(()));
+        assert_eq!(entry(0x10, 0x1f).validate(&layout), Ok(()));
+        assert_eq!(
+            entry(0x10, 0xf).validate(&layout),
+            Err(ProofError::InvalidEntryRange {
+                address_low: 0x10,
+                address_high: 0xf,
+            })
+        );
+        assert_eq!(
+            entry(0x20, 0x3f).validate(&layout),
+            Err(ProofError::EntryOutOfRange {
+                address_low: 0x20,
+                address_high: 0x3f,
+            })
+        );
+        assert_eq!(
+            entry(0x8, 0x17).validate(&layout),
+            Err(ProofError::MisalignedEntry {
+                address_low: 0x8,
+                address_high: 0x17,
+            })
+        );
+        assert_eq!(
+            entry(0x5, 0x5).validate(&layout),
+            Err(ProofError::MisalignedEntry {
+                address_low: 0x5,
+                address_high: 0x5,
+            })
+        );
+
+        assert_eq!(
+            Multiproof::try_new([entry(0x10, 0xf)]),
+            Err(ProofError::InvalidEntryRange {
+                address_low: 0x10,
+                address_high: 0xf,
+            })
+        );
+        let multiproof = Multiproof::try_new([entry(0x0, 0x0), entry(0x4, 0x7)]).unwrap();
+        assert_eq!(
+            multiproof.validate(&layout),
+            Err(ProofError::MisalignedEntry {
+                address_low: 0x4,
+                address_high: 0x7,
+            })
+        );
+    }
+
+    #[test_log::test]
+    fn test_duplicate_entries() {
+        let entry = |hash: u8| MultiproofEntry {
+            address_low: 0x10,
+            address_high: 0x1f,
+            hash: [hash; HASH_SIZE],
+        };
+        let mut multiproof = Multiproof::try_new([entry(1)]).unwrap();
+        assert_eq!(
+            multiproof.insert(entry(1)),
+            Err(ProofError::DuplicateMultiproofEntry {
+                address_low: 0x10,
+                address_high: 0x1f,
+            })
+        );
+        assert_eq!(
+            Multiproof::try_new([entry(1), entry(2)]),
+            Err(ProofError::ConflictingMultiproofEntries {
+                address_low: 0x10,
+                address_high: 0x1f,
+            })
+        );
+        assert_eq!(multiproof.len(), 1);
+        assert_eq!(multiproof.get(0x10, 0x1f), Some(&[1u8; HASH_SIZE]));
+        assert_eq!(multiproof.remove(0x10, 0x1f), Some([1u8; HASH_SIZE]));
+        assert!(multiproof.is_empty());
+    }
+
+    #[test_log::test]
+    fn test_merge_split() {
+        let layout = MemoryLayout::new(10, 4);
+        let image: Vec<u8> = (0..1 << 10).map(|i| (i * 5) as u8).collect();
+        let prover = Prover::<Keccak256>::with_hasher(layout, &image).unwrap();
+        let first = prover.generate(&[0x40]).unwrap();
+        let second = prover.generate(&[0x50, 0x3f0]).unwrap();
+        let all = prover.generate(&[0x40, 0x50, 0x3f0]).unwrap();
+
+        let merged =
+            Multiproof::merge::<Keccak256>(&layout, &[first.clone(), second.clone()]).unwrap();
+        assert_eq!(merged, all);
+        // the same page in both proofs
+        let merged =
+            Multiproof::merge::<Keccak256>(&layout, &[all.clone(), second.clone()]).unwrap();
+        assert_eq!(merged, all);
+
+        let (page_cache, multiproof) = &all;
+        assert_eq!(
+            Multiproof::split::<Keccak256>(&layout, page_cache, multiproof, &[0x50, 0x3f0]),
+            Ok(second.clone())
+        );
+        assert_eq!(
+            Multiproof::split::<Keccak256>(&layout, page_cache, multiproof, &[0x40]),
+            Ok(first.clone())
+        );
+        assert_eq!(
+            Multiproof::split::<Keccak256>(&layout, page_cache, multiproof, &[0x60]),
+            Err(ProofError::MissingPage { address: 0x60 })
+        );
+
+        // the proofs must lead to the same root
+        let other: Vec<u8> = image.iter().map(|byte| byte ^ 1).collect();
+        let other = Prover::<Keccak256>::with_hasher(layout, &other)
+            .unwrap()
+            .generate(&[0x100])
+            .unwrap();
+        assert!(matches!(
+            Multiproof::merge::<Keccak256>(&layout, &[first.clone(), other]),
+            Err(ProofError::RootMismatch { .. })
+        ));
+        // an incomplete proof can't be merged
+        let (page_cache, mut multiproof) = second.clone();
+        multiproof.remove(0x40, 0x40);
+        assert!(matches!(
+            Multiproof::merge::<Keccak256>(&layout, &[(page_cache, multiproof)]),
+            Err(ProofError::MissingNode { .. })
+        ));
+
+        // nor a forged page shadowed by the root entry
+        let (mut page_cache, mut multiproof) = first;
+        page_cache.get_mut(0x40).unwrap().data = vec![0xeeu8; 16];
+        multiproof
+            .insert(MultiproofEntry::new(
+                &layout,
+                layout.depth(),
+                0,
+                prover.root(),
+            ))
+            .unwrap();
+        let forged = (page_cache, multiproof);
+        assert!(matches!(
+            Multiproof::merge::<Keccak256>(&layout, &[forged.clone(), second]),
+            Err(ProofError::RedundantNode { .. })
+        ));
+        assert!(matches!(
+            Multiproof::split::<Keccak256>(&layout, &forged.0, &forged.1, &[0x40]),
+            Err(ProofError::RedundantNode { .. })
+        ));
+    }
 }
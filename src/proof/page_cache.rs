@@ -1,46 +1,208 @@
-use crate::proof::types::{PageAddress, PageData, ProofHash, HASH_SIZE};
-use tiny_keccak::{Hasher, Keccak};
+use crate::proof::{
+    error::ProofError,
+    hasher::MerkleHasher,
+    types::{MemoryLayout, PageAddress, PageData, ProofHash},
+};
+use std::borrow::Cow;
 
 /// A memory page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Page {
+    #[cfg_attr(feature = "serde", serde(with = "crate::proof::hex::bytes"))]
     pub data: PageData,
+    #[cfg_attr(feature = "serde", serde(with = "crate::proof::hex::address"))]
     pub address: PageAddress,
 }
 
 impl Page {
-    pub fn hash(&self) -> ProofHash {
-        let mut hasher = Keccak::v256();
-        let mut output = [0u8; HASH_SIZE];
-        hasher.update(&self.data);
-        hasher.finalize(&mut output);
-        output
+    pub fn hash<H: MerkleHasher>(&self) -> ProofHash {
+        H::hash_leaf(&self.data)
     }
 }
 
-/// A collection of memory pages.
+/// A collection of memory pages. With the `serde` feature it is serialized as the list of its
+/// pages in ascending address order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "Vec<Page>", try_from = "Vec<Page>"))]
 pub struct PageCache {
     pages: Vec<Page>,
 }
 
 impl PageCache {
     /// Create a new page cache with the given pages.
-    /// We keep the pages sorted by address in descending order so that we get the first page by
-    /// popping the last element.
+    /// We keep the pages sorted by address in descending order, the lookups search them with
+    /// `binary_search_by`.
     pub fn new(mut pages: Vec<Page>) -> Self {
         pages.sort_by(|a, b| a.address.cmp(&b.address).reverse());
         Self { pages }
     }
 
-    /// Get next available page from the cache.
-    pub fn get_next(&mut self) -> Option<Page> {
-        self.pages.pop()
+    /// Create a new page cache with the given pages, checking that every page is aligned, has
+    /// the page size and lies within the memory chunk described by `layout`, and that no
+    /// address is supplied twice.
+    pub fn try_new(layout: &MemoryLayout, pages: Vec<Page>) -> Result<Self, ProofError> {
+        for page in &pages {
+            if !layout.is_page_aligned(page.address) {
+                return Err(ProofError::MisalignedPage {
+                    address: page.address,
+                });
+            }
+            if page.address > layout.last_address() {
+                return Err(ProofError::PageOutOfRange {
+                    address: page.address,
+                });
+            }
+            if page.data.len() as u64 != layout.page_size() {
+                return Err(ProofError::InvalidPageSize {
+                    address: page.address,
+                    size: page.data.len(),
+                });
+            }
+        }
+        let page_cache = Self::new(pages);
+        if let Some(pair) = page_cache
+            .pages
+            .windows(2)
+            .find(|pair| pair[0].address == pair[1].address)
+        {
+            return Err(ProofError::OverlappingPages {
+                address: pair[0].address,
+            });
+        }
+        Ok(page_cache)
     }
 
-    /// Check if the cache has the next page necessary for calculating the hash.
-    /// The cache may not have all the pages. Multiproof should complement the missing pages.
-    pub fn has_next(&self, address: PageAddress) -> bool {
+    /// Number of pages in the cache.
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// Iterate over the pages in ascending address order.
+    pub fn iter(&self) -> impl Iterator<Item = &Page> {
+        self.pages.iter().rev()
+    }
+
+    /// Get the page at `address`.
+    pub fn get(&self, address: PageAddress) -> Option<&Page> {
+        // the pages are sorted in descending order
+        self.pages
+            .binary_search_by(|page| address.cmp(&page.address))
+            .ok()
+            .map(|position| &self.pages[position])
+    }
+
+    /// Get the page at `address` for writing.
+    pub fn get_mut(&mut self, address: PageAddress) -> Option<&mut Page> {
+        self.pages
+            .binary_search_by(|page| address.cmp(&page.address))
+            .ok()
+            .map(|position| &mut self.pages[position])
+    }
+
+    /// Check if the cache has any page within `address_low..=address_high`.
+    pub fn has_pages_within(&self, address_low: PageAddress, address_high: PageAddress) -> bool {
+        let position = self
+            .pages
+            .partition_point(|page| page.address > address_high);
         self.pages
-            .last()
-            .map_or(false, |page| page.address == address)
+            .get(position)
+            .is_some_and(|page| page.address >= address_low)
+    }
+}
+
+impl From<PageCache> for Cow<'_, PageCache> {
+    fn from(page_cache: PageCache) -> Self {
+        Cow::Owned(page_cache)
+    }
+}
+
+impl<'a> From<&'a PageCache> for Cow<'a, PageCache> {
+    fn from(page_cache: &'a PageCache) -> Self {
+        Cow::Borrowed(page_cache)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<PageCache> for Vec<Page> {
+    fn from(mut page_cache: PageCache) -> Self {
+        page_cache.pages.reverse();
+        page_cache.pages
+    }
+}
+
+/// Takes the pages without a memory layout, so only the duplicate addresses are rejected.
+#[cfg(feature = "serde")]
+impl TryFrom<Vec<Page>> for PageCache {
+    type Error = ProofError;
+
+    fn try_from(pages: Vec<Page>) -> Result<Self, ProofError> {
+        let page_cache = PageCache::new(pages);
+        match page_cache
+            .pages
+            .windows(2)
+            .find(|pair| pair[0].address == pair[1].address)
+        {
+            Some(pair) => Err(ProofError::OverlappingPages {
+                address: pair[0].address,
+            }),
+            None => Ok(page_cache),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_try_new() {
+        let layout = MemoryLayout::default();
+        let page = |address| Page {
+            data: vec![0u8; layout.page_size() as usize],
+            address,
+        };
+
+        let page_cache =
+            PageCache::try_new(&layout, vec![page(0x8), page(0x0), page(0x1c)]).unwrap();
+        let addresses: Vec<PageAddress> = page_cache.iter().map(|page| page.address).collect();
+        assert_eq!(addresses, [0x0, 0x8, 0x1c]);
+        assert_eq!(page_cache.get(0x8).map(|page| page.address), Some(0x8));
+        assert!(page_cache.get(0x4).is_none());
+        assert!(page_cache.has_pages_within(0x4, 0xb));
+        assert!(page_cache.has_pages_within(0x1c, 0x1f));
+        assert!(!page_cache.has_pages_within(0xc, 0x1b));
+
+        assert_eq!(
+            PageCache::try_new(&layout, vec![page(0x8), page(0x0), page(0x8)]).err(),
+            Some(ProofError::OverlappingPages { address: 0x8 })
+        );
+        assert_eq!(
+            PageCache::try_new(&layout, vec![page(0x0), page(0x6)]).err(),
+            Some(ProofError::MisalignedPage { address: 0x6 })
+        );
+        assert_eq!(
+            PageCache::try_new(&layout, vec![page(0x20)]).err(),
+            Some(ProofError::PageOutOfRange { address: 0x20 })
+        );
+        assert_eq!(
+            PageCache::try_new(
+                &layout,
+                vec![Page {
+                    data: vec![0u8; 8],
+                    address: 0x4,
+                }]
+            )
+            .err(),
+            Some(ProofError::InvalidPageSize {
+                address: 0x4,
+                size: 8
+            })
+        );
     }
 }
@@ -0,0 +1,91 @@
+//! Helpers spreading the hashing over the available cores when the `parallel` feature is
+//! enabled. Without the feature, everything runs on the calling thread. Either way the results
+//! come in the same order, so the hashes don't depend on the feature.
+
+/// Minimum number of items handed to a thread, below which spawning it doesn't pay off.
+#[cfg(feature = "parallel")]
+const MIN_ITEMS_PER_THREAD: usize = 64;
+
+/// Number of threads to use.
+#[cfg(feature = "parallel")]
+pub fn threads() -> usize {
+    std::thread::available_parallelism().map_or(1, usize::from)
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn threads() -> usize {
+    1
+}
+
+/// Applies `f` to every item and returns the results in the order of the items.
+#[cfg(feature = "parallel")]
+pub fn map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = threads().min(items.len() / MIN_ITEMS_PER_THREAD);
+    if threads < 2 {
+        return items.iter().map(f).collect();
+    }
+    let chunk_size = items.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let f = &f;
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<R>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    F: Fn(&T) -> R,
+{
+    items.iter().map(f).collect()
+}
+
+/// Runs `a` and `b`, possibly at the same time, and returns their results.
+#[cfg(feature = "parallel")]
+pub fn join<A, B, RA, RB>(a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB,
+    RA: Send,
+{
+    std::thread::scope(|scope| {
+        let a = scope.spawn(a);
+        let b = b();
+        (a.join().unwrap(), b)
+    })
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn join<A, B, RA, RB>(a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA,
+    B: FnOnce() -> RB,
+{
+    (a(), b())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_map() {
+        for count in [0, 1, 63, 64, 1000, 4097] {
+            let items: Vec<u64> = (0..count).collect();
+            let expected: Vec<u64> = items.iter().map(|item| item * 3).collect();
+            assert_eq!(map(&items, |item| item * 3), expected);
+        }
+        assert_eq!(join(|| 1, || 2), (1, 2));
+    }
+}
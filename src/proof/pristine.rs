@@ -0,0 +1,65 @@
+use crate::proof::{
+    hasher::{Keccak256, MerkleHasher},
+    types::{MemoryLayout, ProofHash},
+};
+use std::{marker::PhantomData, sync::OnceLock};
+
+/// Hashes of the pristine (zero-filled) subtrees at every level of the tree described by
+/// `layout`, 0 being the level of the pages. The hashes are calculated on the first access.
+pub struct PristineHashes<H: MerkleHasher = Keccak256> {
+    layout: MemoryLayout,
+    hashes: Vec<OnceLock<ProofHash>>,
+    hasher: PhantomData<H>,
+}
+
+impl<H: MerkleHasher> PristineHashes<H> {
+    pub fn new(layout: MemoryLayout) -> Self {
+        Self {
+            layout,
+            hashes: vec![OnceLock::new(); layout.depth() + 1],
+            hasher: PhantomData,
+        }
+    }
+
+    /// Returns the hash of a pristine subtree at `level`.
+    pub fn get(&self, level: usize) -> ProofHash {
+        *self.hashes[level].get_or_init(|| {
+            if level == 0 {
+                H::hash_leaf(&vec![0u8; self.layout.page_size() as usize])
+            } else {
+                let child = self.get(level - 1);
+                H::hash_node(&child, &child)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::{
+        hasher::Keccak256,
+        merkle_proof::MerkleProof,
+        multiproof::Multiproof,
+        page_cache::{Page, PageCache},
+    };
+
+    #[test_log::test]
+    fn test_pristine_hashes() {
+        let layout = MemoryLayout::new(8, 3);
+        let pages = (0..1 << layout.depth())
+            .map(|index| Page {
+                data: vec![0u8; 8],
+                address: index << 3,
+            })
+            .collect();
+        let mut merkle_proof =
+            MerkleProof::<Keccak256>::with_hasher(layout, PageCache::new(pages), Multiproof::new());
+        let pristine = PristineHashes::<Keccak256>::new(layout);
+        assert_eq!(
+            Ok(pristine.get(layout.depth())),
+            merkle_proof.calculate_root()
+        );
+        assert_eq!(pristine.get(0), Keccak256::hash_leaf(&[0u8; 8]));
+    }
+}
@@ -0,0 +1,325 @@
+use crate::proof::{
+    error::ProofError,
+    hasher::{Keccak256, MerkleHasher},
+    multiproof::{Multiproof, MultiproofEntry},
+    page_cache::{Page, PageCache},
+    parallel,
+    pristine::PristineHashes,
+    types::{MemoryLayout, PageAddress, ProofHash},
+};
+use std::marker::PhantomData;
+
+/// Generates proofs for the memory chunk described by `layout` from its `image`. A proof
+/// consists of the requested pages and the minimal multiproof: the hashes of the largest
+/// subtrees that contain none of the requested pages and are siblings of the subtrees that do.
+/// The tree is built once, when the prover is created, so the root and the proofs are read from
+/// the hashes of its nodes without rehashing the image.
+/// An image shorter than the memory chunk is padded with zeros: only the nodes covering the
+/// image are hashed, the ones beyond it are pristine, so a large memory chunk is fine.
+/// If `omit_pristine` is set, the hashes of the pristine (zero-filled) subtrees are left out of
+/// the multiproof.
+pub struct Prover<'a, H: MerkleHasher = Keccak256> {
+    layout: MemoryLayout,
+    image: &'a [u8],
+    /// Hashes of the nodes of every level that cover the image by index, 0 being the level of
+    /// the pages.
+    levels: Vec<Vec<ProofHash>>,
+    pristine: PristineHashes<H>,
+    omit_pristine: bool,
+    hasher: PhantomData<H>,
+}
+
+impl<'a> Prover<'a> {
+    /// Creates a prover using Keccak-256.
+    pub fn new(layout: MemoryLayout, image: &'a [u8]) -> Result<Self, ProofError> {
+        Self::with_hasher(layout, image)
+    }
+}
+
+impl<'a, H: MerkleHasher> Prover<'a, H> {
+    /// Creates a prover using the hash function `H` and builds the tree of the image. The image
+    /// must fit into the memory chunk, the rest of the memory is pristine.
+    pub fn with_hasher(layout: MemoryLayout, image: &'a [u8]) -> Result<Self, ProofError> {
+        if image.len() as u128 > layout.last_address() as u128 + 1 {
+            return Err(ProofError::InvalidImageSize { size: image.len() });
+        }
+        let pristine = PristineHashes::<H>::new(layout);
+        let page_size = layout.page_size() as usize;
+        let pages: Vec<&[u8]> = image.chunks(page_size).collect();
+        let pristine_page = pristine.get(0);
+        let mut levels = vec![parallel::map(&pages, |data| {
+            if data.iter().all(|byte| *byte == 0) {
+                pristine_page
+            } else if data.len() < page_size {
+                let mut page = data.to_vec();
+                page.resize(page_size, 0);
+                H::hash_leaf(&page)
+            } else {
+                H::hash_leaf(data)
+            }
+        })];
+        for level in 1..=layout.depth() {
+            log::debug!(">>> Building the tree, level: {}", level);
+            let (pristine_child, pristine_parent) = (pristine.get(level - 1), pristine.get(level));
+            let children: Vec<&[ProofHash]> = levels[level - 1].chunks(2).collect();
+            let parents = parallel::map(&children, |pair| {
+                // the last node covering the image may have a pristine right sibling
+                let right = pair.get(1).unwrap_or(&pristine_child);
+                if pair[0] == pristine_child && *right == pristine_child {
+                    pristine_parent
+                } else {
+                    H::hash_node(&pair[0], right)
+                }
+            });
+            levels.push(parents);
+        }
+        Ok(Self {
+            layout,
+            image,
+            levels,
+            pristine,
+            omit_pristine: false,
+            hasher: PhantomData,
+        })
+    }
+
+    /// Enables or disables leaving the pristine subtrees out of the generated multiproofs. Such
+    /// multiproofs must be verified with `MerkleProof::pristine` enabled.
+    pub fn pristine(mut self, omit_pristine: bool) -> Self {
+        self.omit_pristine = omit_pristine;
+        self
+    }
+
+    /// Returns the hash of the node at `index` at `level`, 0 being the level of the pages.
+    fn node_hash(&self, level: usize, index: u64) -> ProofHash {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.levels[level].get(index))
+            .copied()
+            .unwrap_or_else(|| self.pristine.get(level))
+    }
+
+    /// Checks whether the node at `index` at `level` is a pristine subtree.
+    fn is_pristine(&self, level: usize, index: u64) -> bool {
+        self.node_hash(level, index) == self.pristine.get(level)
+    }
+
+    /// Returns the Merkle tree root of the image.
+    pub fn root(&self) -> ProofHash {
+        self.node_hash(self.layout.depth(), 0)
+    }
+
+    /// Collects the multiproof entries for the subtree at `index` at `level`. `pages` are the
+    /// sorted indices of the requested pages within the subtree.
+    fn collect_entries(
+        &self,
+        level: usize,
+        index: u64,
+        pages: &[u64],
+        entries: &mut Vec<(usize, u64, ProofHash)>,
+    ) {
+        if pages.is_empty() {
+            if !(self.omit_pristine && self.is_pristine(level, index)) {
+                entries.push((level, index, self.node_hash(level, index)));
+            }
+            return;
+        }
+        if level == 0 {
+            return;
+        }
+        let right_index = (index << 1) | 1;
+        let split = pages.partition_point(|page| page >> (level - 1) < right_index);
+        self.collect_entries(level - 1, index << 1, &pages[..split], entries);
+        self.collect_entries(level - 1, right_index, &pages[split..], entries);
+    }
+
+    /// Generates the page cache with the given pages and the multiproof needed to calculate the
+    /// root from them.
+    pub fn generate(&self, pages: &[PageAddress]) -> Result<(PageCache, Multiproof), ProofError> {
+        let mut indices = Vec::with_capacity(pages.len());
+        for &address in pages {
+            if !self.layout.is_page_aligned(address) {
+                return Err(ProofError::MisalignedPage { address });
+            }
+            if address > self.layout.last_address() {
+                return Err(ProofError::PageOutOfRange { address });
+            }
+            indices.push(address >> self.layout.page_log2_size());
+        }
+        indices.sort_unstable();
+        indices.dedup();
+
+        let mut entries = Vec::new();
+        self.collect_entries(self.layout.depth(), 0, &indices, &mut entries);
+        let multiproof =
+            Multiproof::try_new(entries.into_iter().map(|(level, index, hash)| {
+                MultiproofEntry::new(&self.layout, level, index, hash)
+            }))?;
+
+        let page_size = self.layout.page_size() as usize;
+        let pages = indices
+            .into_iter()
+            .map(|index| {
+                let address = index << self.layout.page_log2_size();
+                let mut data = vec![0u8; page_size];
+                if address < self.image.len() as u64 {
+                    let image = &self.image[address as usize..];
+                    let length = image.len().min(page_size);
+                    data[..length].copy_from_slice(&image[..length]);
+                }
+                Page { data, address }
+            })
+            .collect();
+        Ok((PageCache::new(pages), multiproof))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::{hasher::HashAlgorithm, merkle_proof::MerkleProof, stream::root_from_slice};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn test_image(layout: &MemoryLayout) -> Vec<u8> {
+        (0..=layout.last_address()).map(|i| (i * 7) as u8).collect()
+    }
+
+    #[test_log::test]
+    fn test_generate_all_subsets() {
+        let layout = MemoryLayout::default();
+        let image = test_image(&layout);
+        let prover = Prover::new(layout, &image).unwrap();
+        let root = prover.root();
+        let page_count = 1u64 << layout.depth();
+
+        for subset in 0u64..(1 << page_count) {
+            let pages: Vec<PageAddress> = (0..page_count)
+                .filter(|page| subset & (1 << page) != 0)
+                .map(|page| page << layout.page_log2_size())
+                .collect();
+            let (page_cache, multiproof) = prover.generate(&pages).unwrap();
+            let mut merkle_proof = MerkleProof::new(layout, page_cache, multiproof).strict(true);
+            assert_eq!(
+                merkle_proof.calculate_root(),
+                Ok(root),
+                "pages {:x?}",
+                pages
+            );
+        }
+    }
+
+    #[test_log::test]
+    fn test_generate_minimal() {
+        let layout = MemoryLayout::new(10, 4);
+        let image = test_image(&layout);
+        let prover = Prover::<Keccak256>::with_hasher(layout, &image).unwrap();
+
+        let (page_cache, multiproof) = prover.generate(&[0x40, 0x50, 0x3f0]).unwrap();
+        assert_eq!(page_cache.len(), 3);
+        // siblings of the subtrees holding the pages: levels 1 to 4 for the first two pages,
+        // levels 0 to 4 for the last one
+        assert_eq!(multiproof.len(), 9);
+
+        let mut merkle_proof =
+            MerkleProof::<Keccak256>::with_hasher(layout, page_cache, multiproof).strict(true);
+        assert_eq!(merkle_proof.calculate_root(), Ok(prover.root()));
+    }
+
+    /// Keccak-256 counting the hashed pages.
+    struct CountingHasher;
+
+    static HASHED_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+    impl MerkleHasher for CountingHasher {
+        const ALGORITHM: HashAlgorithm = HashAlgorithm::Keccak256;
+
+        fn hash_leaf(data: &[u8]) -> ProofHash {
+            HASHED_PAGES.fetch_add(1, Ordering::Relaxed);
+            Keccak256::hash_leaf(data)
+        }
+
+        fn hash_node(left: &ProofHash, right: &ProofHash) -> ProofHash {
+            Keccak256::hash_node(left, right)
+        }
+    }
+
+    #[test_log::test]
+    fn test_tree_built_once() {
+        let layout = MemoryLayout::new(10, 4);
+        let mut image = test_image(&layout);
+        image[0x100..0x200].fill(0);
+        let prover = Prover::<CountingHasher>::with_hasher(layout, &image).unwrap();
+        // the pristine page is hashed once, for the pristine hashes
+        assert_eq!(HASHED_PAGES.load(Ordering::Relaxed), 64 - 16 + 1);
+        assert_eq!(prover.root(), Prover::new(layout, &image).unwrap().root());
+
+        let prover = prover.pristine(true);
+        for pages in [&[0x40, 0x50, 0x3f0][..], &[0x100], &[]] {
+            let (page_cache, multiproof) = prover.generate(pages).unwrap();
+            let mut merkle_proof =
+                MerkleProof::<CountingHasher>::with_hasher(layout, &page_cache, &multiproof)
+                    .strict(true)
+                    .pristine(true);
+            assert_eq!(merkle_proof.calculate_root(), Ok(prover.root()));
+        }
+        let hashed_pages = HASHED_PAGES.load(Ordering::Relaxed);
+        prover.root();
+        prover.generate(&[0x40, 0x3f0]).unwrap();
+        assert_eq!(HASHED_PAGES.load(Ordering::Relaxed), hashed_pages);
+    }
+
+    #[test_log::test]
+    fn test_short_image() {
+        let layout = MemoryLayout::new(10, 4);
+        let mut image = test_image(&layout);
+        for length in [0x3ff, 0x123, 0x40, 1, 0] {
+            image[length..].fill(0);
+            let padded = Prover::new(layout, &image).unwrap();
+            let prover = Prover::new(layout, &image[..length]).unwrap();
+            assert_eq!(prover.root(), padded.root(), "length {:x}", length);
+            for pages in [&[0x40, 0x120, 0x3f0][..], &[0x0], &[]] {
+                assert_eq!(
+                    prover.generate(pages),
+                    padded.generate(pages),
+                    "length {:x}, pages {:x?}",
+                    length,
+                    pages
+                );
+            }
+        }
+
+        // only the pages of the image are hashed, so a huge memory chunk is fine
+        let image = vec![1u8; 3 << 12];
+        let prover = Prover::new(MemoryLayout::new(64, 12), &image).unwrap();
+        assert_eq!(
+            prover.root(),
+            root_from_slice::<Keccak256>(&image, &MemoryLayout::new(64, 12)).unwrap()
+        );
+        let (page_cache, multiproof) = prover.generate(&[0x2000, 0xffff_f000]).unwrap();
+        let mut merkle_proof =
+            MerkleProof::new(MemoryLayout::new(64, 12), page_cache, multiproof).strict(true);
+        assert_eq!(merkle_proof.calculate_root(), Ok(prover.root()));
+    }
+
+    #[test_log::test]
+    fn test_generate_invalid_input() {
+        let layout = MemoryLayout::default();
+        let image = test_image(&layout);
+        let mut larger = image.clone();
+        larger.push(0);
+        assert_eq!(
+            Prover::new(layout, &larger).err(),
+            Some(ProofError::InvalidImageSize { size: 33 })
+        );
+
+        let prover = Prover::new(layout, &image).unwrap();
+        assert_eq!(
+            prover.generate(&[0x6]).err(),
+            Some(ProofError::MisalignedPage { address: 0x6 })
+        );
+        assert_eq!(
+            prover.generate(&[0x20]).err(),
+            Some(ProofError::PageOutOfRange { address: 0x20 })
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use crate::proof::{
+    hasher::MerkleHasher,
+    parallel,
+    pristine::PristineHashes,
+    types::{MemoryLayout, PageData, ProofHash},
+};
+use std::io::{self, Read};
+
+/// Number of pages read before they are hashed, so that the `parallel` feature can hash them on
+/// several threads.
+const BATCH_PAGES: usize = 256;
+
+/// Hashes of the complete subtrees seen so far as `(level, hash)` pairs. The levels strictly
+/// decrease towards the top of the stack, so it never holds more than `depth + 1` hashes.
+struct SubtreeStack<H: MerkleHasher> {
+    stack: Vec<(usize, ProofHash)>,
+    pristine: PristineHashes<H>,
+}
+
+impl<H: MerkleHasher> SubtreeStack<H> {
+    fn new(layout: MemoryLayout) -> Self {
+        Self {
+            stack: Vec::with_capacity(layout.depth() + 1),
+            pristine: PristineHashes::new(layout),
+        }
+    }
+
+    /// Pushes the hash of the next subtree and merges the complete subtrees.
+    fn push(&mut self, mut level: usize, mut hash: ProofHash) {
+        while let Some((_, left)) = self.stack.pop_if(|(top, _)| *top == level) {
+            hash = H::hash_node(&left, &hash);
+            level += 1;
+        }
+        self.stack.push((level, hash));
+    }
+}
+
+/// Reads into `buffer` until it is full or the reader is exhausted, returning the number of bytes
+/// read.
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(count) => filled += count,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(filled)
+}
+
+/// Calculates the Merkle tree root of the memory image read from `reader`, using the hash
+/// function `H`. The image is consumed page by page and only the hashes of `O(depth)` subtrees
+/// are kept. An image shorter than the memory chunk is padded with zeros, the pristine part of
+/// the tree is not hashed at all.
+pub fn root_from_reader<H: MerkleHasher>(
+    mut reader: impl Read,
+    layout: &MemoryLayout,
+) -> io::Result<ProofHash> {
+    let page_size = usize::try_from(layout.page_size())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the page is too large"))?;
+    let page_count = 1u128 << layout.depth();
+    let mut stack = SubtreeStack::<H>::new(*layout);
+    let mut position = 0u128;
+    let mut batch: Vec<PageData> = Vec::with_capacity(BATCH_PAGES);
+    let mut exhausted = false;
+    while !exhausted && position < page_count {
+        batch.clear();
+        while batch.len() < BATCH_PAGES && position + (batch.len() as u128) < page_count {
+            let mut page = vec![0u8; page_size];
+            let count = read_full(&mut reader, &mut page)?;
+            if count == 0 {
+                exhausted = true;
+                break;
+            }
+            batch.push(page);
+            if count < page_size {
+                exhausted = true;
+                break;
+            }
+        }
+        for hash in parallel::map(&batch, |page| H::hash_leaf(page)) {
+            stack.push(0, hash);
+        }
+        position += batch.len() as u128;
+    }
+    if position == page_count && read_full(&mut reader, &mut [0u8; 1])? != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the image doesn't fit into 2^{} bytes",
+                layout.memory_log2_size()
+            ),
+        ));
+    }
+
+    // the rest of the memory is pristine: complete the tree with the largest aligned pristine
+    // subtrees
+    while position < page_count {
+        let level = if position == 0 {
+            layout.depth()
+        } else {
+            position.trailing_zeros() as usize
+        };
+        stack.push(level, stack.pristine.get(level));
+        position += 1 << level;
+    }
+    Ok(stack.stack[0].1)
+}
+
+/// Calculates the Merkle tree root of the memory image held in `image`, using the hash function
+/// `H`. The image may be a memory-mapped file: the independent subtrees are read at random and
+/// hashed on several threads with the `parallel` feature. An image shorter than the memory chunk
+/// is padded with zeros.
+pub fn root_from_slice<H: MerkleHasher>(
+    image: &[u8],
+    layout: &MemoryLayout,
+) -> io::Result<ProofHash> {
+    if image.len() as u128 > layout.last_address() as u128 + 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the image doesn't fit into 2^{} bytes",
+                layout.memory_log2_size()
+            ),
+        ));
+    }
+    let pristine = PristineHashes::<H>::new(*layout);
+    Ok(slice_node_hash(
+        image,
+        layout,
+        &pristine,
+        layout.depth(),
+        0,
+        parallel::threads(),
+    ))
+}
+
+/// Calculates the hash of the node at `index` at `level` from the part of the image it covers.
+fn slice_node_hash<H: MerkleHasher>(
+    image: &[u8],
+    layout: &MemoryLayout,
+    pristine: &PristineHashes<H>,
+    level: usize,
+    index: u64,
+    threads: usize,
+) -> ProofHash {
+    let (address_low, address_high) = layout.node_range(level, index);
+    if address_low as u128 >= image.len() as u128 {
+        return pristine.get(level);
+    }
+    if level == 0 {
+        let data = &image
+            [address_low as usize..image.len().min((address_high as usize).saturating_add(1))];
+        if data.len() as u64 == layout.page_size() {
+            return H::hash_leaf(data);
+        }
+        let mut page = data.to_vec();
+        page.resize(layout.page_size() as usize, 0);
+        return H::hash_leaf(&page);
+    }
+    let child =
+        |index, threads| slice_node_hash(image, layout, pristine, level - 1, index, threads);
+    let (left, right) = if threads < 2 {
+        (child(index << 1, 1), child((index << 1) | 1, 1))
+    } else {
+        parallel::join(
+            || child(index << 1, threads / 2),
+            || child((index << 1) | 1, threads - threads / 2),
+        )
+    };
+    H::hash_node(&left, &right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::{hasher::Keccak256, prover::Prover};
+
+    #[test_log::test]
+    fn test_root_from_reader() {
+        let layout = MemoryLayout::new(16, 6);
+        let mut image: Vec<u8> = (0..1u32 << 16).map(|i| (i * 7 + (i >> 9)) as u8).collect();
+        let root = Prover::<Keccak256>::with_hasher(layout, &image)
+            .unwrap()
+            .root();
+        assert_eq!(
+            root_from_reader::<Keccak256>(&image[..], &layout).unwrap(),
+            root
+        );
+        assert_eq!(root_from_slice::<Keccak256>(&image, &layout).unwrap(), root);
+
+        // a shorter image is padded with zeros
+        for length in [0xffff, 0x8000, 0x1234, 0x40, 1, 0] {
+            image[length..].fill(0);
+            let root = Prover::<Keccak256>::with_hasher(layout, &image)
+                .unwrap()
+                .root();
+            assert_eq!(
+                root_from_reader::<Keccak256>(&image[..length], &layout).unwrap(),
+                root,
+                "length {:x}",
+                length
+            );
+            assert_eq!(
+                root_from_slice::<Keccak256>(&image[..length], &layout).unwrap(),
+                root,
+                "length {:x}",
+                length
+            );
+        }
+
+        image.push(0);
+        assert!(root_from_reader::<Keccak256>(&image[..], &layout).is_err());
+        assert!(root_from_slice::<Keccak256>(&image, &layout).is_err());
+    }
+
+    #[test_log::test]
+    fn test_root_from_reader_sparse() {
+        // only the pages that are read are hashed, so a huge memory chunk is fine
+        let layout = MemoryLayout::new(64, 12);
+        let image = vec![1u8; 3 << 12];
+        let expected = {
+            let pristine = PristineHashes::<Keccak256>::new(layout);
+            let page = Keccak256::hash_leaf(&image[..1 << 12]);
+            let mut hash = Keccak256::hash_node(
+                &Keccak256::hash_node(&page, &page),
+                &Keccak256::hash_node(&page, &pristine.get(0)),
+            );
+            for level in 2..layout.depth() {
+                hash = Keccak256::hash_node(&hash, &pristine.get(level));
+            }
+            hash
+        };
+        assert_eq!(
+            root_from_reader::<Keccak256>(&image[..], &layout).unwrap(),
+            expected
+        );
+        assert_eq!(
+            root_from_slice::<Keccak256>(&image, &layout).unwrap(),
+            expected
+        );
+    }
+}
@@ -1,7 +1,145 @@
+use crate::proof::error::ProofError;
+
+/// Default geometry of the Merkle tree, see `MemoryLayout::default`.
 pub const MEMORY_LOG2_SIZE: usize = 5;
 pub const PAGE_LOG2_SIZE: usize = 2;
+
+/// All the supported hash functions produce 32-byte digests.
 pub const HASH_SIZE: usize = 32;
 
-pub type PageData = [u8; 1 << PAGE_LOG2_SIZE];
+pub type PageData = Vec<u8>;
 pub type ProofHash = [u8; HASH_SIZE];
 pub type PageAddress = u64;
+
+/// Geometry of the Merkle tree: the memory chunk of `2^memory_log2_size` bytes is divided into
+/// pages of `2^page_log2_size` bytes, which are the leaves of the tree. A memory chunk of a
+/// single page is a tree of a single leaf, whose root is the hash of the page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLayout {
+    memory_log2_size: usize,
+    page_log2_size: usize,
+}
+
+impl MemoryLayout {
+    /// Creates the layout, see `try_new` for the valid geometries.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the geometry is invalid. Within a constant, it fails to compile instead.
+    pub const fn new(memory_log2_size: usize, page_log2_size: usize) -> Self {
+        match Self::try_new(memory_log2_size, page_log2_size) {
+            Ok(layout) => layout,
+            Err(_) => panic!("invalid memory layout"),
+        }
+    }
+
+    /// Creates the layout, failing if the page is larger than the memory chunk or the memory
+    /// chunk doesn't fit into the 64-bit address space. The page size itself must fit into
+    /// `u64`, so the memory chunk of `2^64` bytes can't be a single page.
+    pub const fn try_new(
+        memory_log2_size: usize,
+        page_log2_size: usize,
+    ) -> Result<Self, ProofError> {
+        if memory_log2_size > PageAddress::BITS as usize
+            || page_log2_size > memory_log2_size
+            || page_log2_size >= u64::BITS as usize
+        {
+            return Err(ProofError::InvalidLayout {
+                memory_log2_size,
+                page_log2_size,
+            });
+        }
+        Ok(Self {
+            memory_log2_size,
+            page_log2_size,
+        })
+    }
+
+    pub fn memory_log2_size(&self) -> usize {
+        self.memory_log2_size
+    }
+
+    pub fn page_log2_size(&self) -> usize {
+        self.page_log2_size
+    }
+
+    pub fn hash_size(&self) -> usize {
+        HASH_SIZE
+    }
+
+    /// Page size in bytes.
+    pub fn page_size(&self) -> u64 {
+        1 << self.page_log2_size
+    }
+
+    /// Address of the last byte of the memory chunk. The memory size itself may not fit into
+    /// `PageAddress` for a 64-bit address space.
+    pub fn last_address(&self) -> PageAddress {
+        Self::mask(self.memory_log2_size)
+    }
+
+    /// Index of the last node at `level`, 0 being the level of the pages.
+    pub fn last_index(&self, level: usize) -> u64 {
+        self.last_address()
+            .checked_shr((self.page_log2_size + level) as u32)
+            .unwrap_or(0)
+    }
+
+    /// Memory range `address_low..=address_high` covered by the node at `index` at `level`, 0
+    /// being the level of the pages.
+    pub fn node_range(&self, level: usize, index: u64) -> (PageAddress, PageAddress) {
+        let log2_size = self.page_log2_size + level;
+        let address_low = index.checked_shl(log2_size as u32).unwrap_or(0);
+        (address_low, address_low | Self::mask(log2_size))
+    }
+
+    fn mask(log2_size: usize) -> PageAddress {
+        ((1u128 << log2_size) - 1) as PageAddress
+    }
+
+    /// Number of levels above the pages, i.e. the level of the root.
+    pub fn depth(&self) -> usize {
+        self.memory_log2_size - self.page_log2_size
+    }
+
+    /// Checks whether `address` starts a page.
+    pub fn is_page_aligned(&self, address: PageAddress) -> bool {
+        address & (self.page_size() - 1) == 0
+    }
+}
+
+impl MemoryLayout {
+    /// Layout of the Cartesi machine Merkle tree: the 64-bit address space divided into 4 KiB
+    /// pages, to be used with the `Cartesi` hasher.
+    pub const CARTESI: Self = Self::new(64, 12);
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        Self::new(MEMORY_LOG2_SIZE, PAGE_LOG2_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_try_new() {
+        assert_eq!(MemoryLayout::try_new(5, 2), Ok(MemoryLayout::default()));
+        for (memory_log2_size, page_log2_size) in [(0, 0), (12, 12), (64, 12), (64, 63), (64, 0)] {
+            let layout = MemoryLayout::try_new(memory_log2_size, page_log2_size).unwrap();
+            assert_eq!(layout.depth(), memory_log2_size - page_log2_size);
+            assert_eq!(layout.last_index(layout.depth()), 0);
+        }
+        for (memory_log2_size, page_log2_size) in [(2, 5), (65, 12), (64, 64), (70, 70)] {
+            assert_eq!(
+                MemoryLayout::try_new(memory_log2_size, page_log2_size),
+                Err(ProofError::InvalidLayout {
+                    memory_log2_size,
+                    page_log2_size
+                })
+            );
+        }
+    }
+}
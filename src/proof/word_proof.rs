@@ -0,0 +1,152 @@
+use crate::proof::{
+    error::ProofError,
+    hasher::WordHasher,
+    multiproof::{strict_root, KnownNodes, Multiproof, MultiproofEntry},
+    page_cache::{Page, PageCache},
+    types::{MemoryLayout, PageAddress},
+};
+use std::collections::BTreeSet;
+
+/// Returns the layout whose pages are the words of the hasher `H`. With a word hasher, the
+/// subtree of a page is part of the tree, so the tree described by the returned layout has the
+/// same root. Its proofs are the word-level proofs: the words and the multiproof entries for
+/// the ranges down to a single word, which are verified with `MerkleProof` as usual.
+/// Fails if the words are larger than the pages.
+pub fn word_layout<H: WordHasher>(layout: &MemoryLayout) -> Result<MemoryLayout, ProofError> {
+    MemoryLayout::try_new(layout.page_log2_size(), H::WORD_LOG2_SIZE)?;
+    MemoryLayout::try_new(layout.memory_log2_size(), H::WORD_LOG2_SIZE)
+}
+
+/// Narrows the page-level proof made of `page_cache` and `multiproof` down to the words at
+/// `words`, for the layout returned by `word_layout`. The pages holding none of the words only
+/// contribute their hashes, the others are split into their words. The returned multiproof is
+/// minimal.
+pub fn word_proof<H: WordHasher>(
+    layout: &MemoryLayout,
+    page_cache: &PageCache,
+    multiproof: &Multiproof,
+    words: &[PageAddress],
+) -> Result<(PageCache, Multiproof), ProofError> {
+    let word_layout = word_layout::<H>(layout)?;
+    // the levels of the subtree of a page
+    let page_depth = layout.page_log2_size() - H::WORD_LOG2_SIZE;
+    let page_mask = layout.page_size() - 1;
+
+    let mut addresses = words.to_vec();
+    addresses.sort_unstable();
+    addresses.dedup();
+    let mut pages = BTreeSet::new();
+    for &address in &addresses {
+        if !word_layout.is_page_aligned(address) {
+            return Err(ProofError::MisalignedPage { address });
+        }
+        if address > word_layout.last_address() {
+            return Err(ProofError::PageOutOfRange { address });
+        }
+        let page = address & !page_mask;
+        if page_cache.get(page).is_none() {
+            return Err(ProofError::MissingPage { address: page });
+        }
+        pages.insert(page);
+    }
+
+    strict_root::<H>(layout, page_cache, multiproof)?;
+    // the entries keep their nodes, which are deeper in the tree of words
+    multiproof.validate(layout)?;
+    let mut entries = Multiproof::try_new(multiproof.iter().map(|entry| {
+        let (level, index) = entry.node(layout).unwrap();
+        MultiproofEntry::new(&word_layout, level + page_depth, index, entry.hash)
+    }))?;
+    let mut all_words = Vec::new();
+    for page in page_cache.iter() {
+        if !pages.contains(&page.address) {
+            entries.insert(MultiproofEntry::new(
+                &word_layout,
+                page_depth,
+                page.address >> layout.page_log2_size(),
+                page.hash::<H>(),
+            ))?;
+            continue;
+        }
+        if page.data.len() as u64 != layout.page_size() {
+            return Err(ProofError::InvalidPageSize {
+                address: page.address,
+                size: page.data.len(),
+            });
+        }
+        all_words.extend(
+            page.data
+                .chunks(1 << H::WORD_LOG2_SIZE)
+                .zip((page.address..).step_by(1 << H::WORD_LOG2_SIZE))
+                .map(|(data, address)| Page {
+                    data: data.to_vec(),
+                    address,
+                }),
+        );
+    }
+    let all_words = PageCache::new(all_words);
+
+    let indices: Vec<u64> = addresses
+        .iter()
+        .map(|address| address >> H::WORD_LOG2_SIZE)
+        .collect();
+    let multiproof =
+        KnownNodes::<H>::new(word_layout, &all_words, &entries).multiproof(&indices)?;
+    let words = addresses
+        .iter()
+        .map(|address| all_words.get(*address).unwrap().clone())
+        .collect();
+    Ok((PageCache::new(words), multiproof))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::{hasher::Cartesi, merkle_proof::MerkleProof, prover::Prover};
+
+    #[test_log::test]
+    fn test_word_proof() {
+        let layout = MemoryLayout::new(12, 6);
+        let image: Vec<u8> = (0..1u32 << 12).map(|i| (i * 13 + (i >> 7)) as u8).collect();
+        let prover = Prover::<Cartesi>::with_hasher(layout, &image).unwrap();
+        let word_layout = word_layout::<Cartesi>(&layout).unwrap();
+        assert_eq!(word_layout, MemoryLayout::new(12, 3));
+        let word_prover = Prover::<Cartesi>::with_hasher(word_layout, &image).unwrap();
+        assert_eq!(word_prover.root(), prover.root());
+
+        let (page_cache, multiproof) = prover.generate(&[0x40, 0x80, 0x240, 0xfc0]).unwrap();
+        let words = [0x48, 0x50, 0x80, 0xff8];
+        let (word_cache, word_multiproof) =
+            word_proof::<Cartesi>(&layout, &page_cache, &multiproof, &words).unwrap();
+        assert_eq!(word_cache.len(), 4);
+        assert!(word_cache.iter().all(|word| word.data.len() == 8));
+        // the same as the minimal proof generated from the words directly
+        assert_eq!(
+            (word_cache.clone(), word_multiproof.clone()),
+            word_prover.generate(&words).unwrap()
+        );
+        let mut merkle_proof =
+            MerkleProof::<Cartesi>::with_hasher(word_layout, &word_cache, &word_multiproof);
+        assert_eq!(merkle_proof.verify(&prover.root()), Ok(()));
+
+        assert_eq!(
+            word_proof::<Cartesi>(&layout, &page_cache, &multiproof, &[0x84]).err(),
+            Some(ProofError::MisalignedPage { address: 0x84 })
+        );
+        assert_eq!(
+            word_proof::<Cartesi>(&layout, &page_cache, &multiproof, &[0x1000]).err(),
+            Some(ProofError::PageOutOfRange { address: 0x1000 })
+        );
+        assert_eq!(
+            word_proof::<Cartesi>(&layout, &page_cache, &multiproof, &[0xc8]).err(),
+            Some(ProofError::MissingPage { address: 0xc0 })
+        );
+        assert_eq!(
+            super::word_layout::<Cartesi>(&MemoryLayout::new(12, 2)),
+            Err(ProofError::InvalidLayout {
+                memory_log2_size: 2,
+                page_log2_size: 3
+            })
+        );
+    }
+}
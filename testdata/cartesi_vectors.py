@@ -0,0 +1,154 @@
+#!/usr/bin/env python3
+"""Generates cartesi_vectors.json: roots of the Cartesi machine Merkle tree for a few sparse
+memories, calculated independently of the Rust code.
+
+The tree is a binary Merkle tree over the 2^64 bytes of the machine memory with 8-byte words as
+the leaves. A leaf is the Keccak-256 hash of the word, an internal node is the Keccak-256 hash of
+the concatenation of its children. The words are stored little-endian.
+
+Python doesn't ship the original Keccak-256 (hashlib's sha3_256 differs in padding), so the
+permutation is implemented below and checked against sha3_256 with the SHA-3 padding.
+
+Usage: python3 testdata/cartesi_vectors.py > testdata/cartesi_vectors.json
+"""
+
+import hashlib
+import json
+
+ROUND_CONSTANTS = [
+    0x0000000000000001, 0x0000000000008082, 0x800000000000808A, 0x8000000080008000,
+    0x000000000000808B, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+    0x000000000000008A, 0x0000000000000088, 0x0000000080008009, 0x000000008000000A,
+    0x000000008000808B, 0x800000000000008B, 0x8000000000008089, 0x8000000000008003,
+    0x8000000000008002, 0x8000000000000080, 0x000000000000800A, 0x800000008000000A,
+    0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008,
+]
+ROTATIONS = [
+    [0, 36, 3, 41, 18],
+    [1, 44, 10, 45, 2],
+    [62, 6, 43, 15, 61],
+    [28, 55, 25, 21, 56],
+    [27, 20, 39, 8, 14],
+]
+MASK = (1 << 64) - 1
+RATE = 136
+
+WORD_LOG2_SIZE = 3
+PAGE_LOG2_SIZE = 12
+MEMORY_LOG2_SIZE = 64
+
+
+def rotate(value, shift):
+    return ((value << shift) | (value >> (64 - shift))) & MASK if shift else value
+
+
+def keccak_f(state):
+    """Keccak-f[1600] on the lanes state[x][y]."""
+    for constant in ROUND_CONSTANTS:
+        c = [state[x][0] ^ state[x][1] ^ state[x][2] ^ state[x][3] ^ state[x][4] for x in range(5)]
+        d = [c[(x - 1) % 5] ^ rotate(c[(x + 1) % 5], 1) for x in range(5)]
+        state = [[state[x][y] ^ d[x] for y in range(5)] for x in range(5)]
+        b = [[0] * 5 for _ in range(5)]
+        for x in range(5):
+            for y in range(5):
+                b[y][(2 * x + 3 * y) % 5] = rotate(state[x][y], ROTATIONS[x][y])
+        state = [
+            [b[x][y] ^ (~b[(x + 1) % 5][y] & b[(x + 2) % 5][y]) for y in range(5)]
+            for x in range(5)
+        ]
+        state[0][0] ^= constant
+    return state
+
+
+def sponge(data, suffix):
+    padded = bytearray(data) + bytes([suffix])
+    padded += bytes(-len(padded) % RATE)
+    padded[-1] |= 0x80
+    state = [[0] * 5 for _ in range(5)]
+    for offset in range(0, len(padded), RATE):
+        block = padded[offset:offset + RATE]
+        for i in range(RATE // 8):
+            state[i % 5][i // 5] ^= int.from_bytes(block[8 * i:8 * i + 8], "little")
+        state = keccak_f(state)
+    return b"".join(state[i % 5][i // 5].to_bytes(8, "little") for i in range(4))
+
+
+def keccak256(data):
+    return sponge(data, 0x01)
+
+
+def self_check():
+    for data in [b"", b"abc", bytes(range(256)) * 3]:
+        assert sponge(data, 0x06) == hashlib.sha3_256(data).digest()
+    assert keccak256(b"").hex() == "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+
+
+def pristine_hashes():
+    """Hashes of the zero-filled subtrees by log2 size, starting with a word."""
+    hashes = {WORD_LOG2_SIZE: keccak256(bytes(1 << WORD_LOG2_SIZE))}
+    for log2_size in range(WORD_LOG2_SIZE + 1, MEMORY_LOG2_SIZE + 1):
+        child = hashes[log2_size - 1]
+        hashes[log2_size] = keccak256(child + child)
+    return hashes
+
+
+def root(words, pristine):
+    """Root of the memory holding `words`, a dict of word values by address."""
+
+    def node(address, log2_size, addresses):
+        if not addresses:
+            return pristine[log2_size]
+        if log2_size == WORD_LOG2_SIZE:
+            return keccak256(words[address].to_bytes(8, "little"))
+        middle = address + (1 << (log2_size - 1))
+        left = [a for a in addresses if a < middle]
+        right = [a for a in addresses if a >= middle]
+        return keccak256(node(address, log2_size - 1, left) + node(middle, log2_size - 1, right))
+
+    return node(0, MEMORY_LOG2_SIZE, sorted(words))
+
+
+def main():
+    self_check()
+    pristine = pristine_hashes()
+    memories = [
+        ("pristine", {}),
+        ("first word", {0x0: 0x0123456789ABCDEF}),
+        ("ram start", {0x80000000: 0x1, 0x80000008: 0xFFFFFFFFFFFFFFFF}),
+        (
+            "scattered",
+            {
+                0x1000: 0x10,
+                0x1FF8: 0x1FF8,
+                0x7FFFF000: 0xDEADBEEF,
+                0x80000000: 0x00000013,
+                0x80001234 & ~7: 0x8000123000000000,
+                0xFFFFFFFFFFFFFFF8: 0xCAFEBABE,
+            },
+        ),
+    ]
+    vectors = {
+        "word_log2_size": WORD_LOG2_SIZE,
+        "page_log2_size": PAGE_LOG2_SIZE,
+        "memory_log2_size": MEMORY_LOG2_SIZE,
+        "pristine": [
+            "0x" + pristine[log2_size].hex()
+            for log2_size in range(WORD_LOG2_SIZE, MEMORY_LOG2_SIZE + 1)
+        ],
+        "memories": [
+            {
+                "name": name,
+                "words": [
+                    {"address": hex(address), "value": "0x%016x" % value}
+                    for address, value in sorted(words.items())
+                ],
+                "root": "0x" + root(words, pristine).hex(),
+            }
+            for name, words in memories
+        ],
+    }
+    print(json.dumps(vectors, indent=2))
+
+
+if __name__ == "__main__":
+    main()